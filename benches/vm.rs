@@ -0,0 +1,88 @@
+//! Microbenchmarks for the scanner, compiler, and VM.
+//!
+//! Run with `cargo bench`. Each benchmark reports the minimum and median
+//! time per iteration over a fixed number of samples.
+
+use std::hint::black_box;
+use std::io;
+use std::time::{Duration, Instant};
+
+use rlox::compiler::Compiler;
+use rlox::scanner::Scanner;
+use rlox::token::TokenKind;
+use rlox::vm::VM;
+
+const SAMPLES: usize = 30;
+
+fn bench<F: FnMut()>(name: &str, mut f: F) {
+    // Warm up caches and the allocator before measuring.
+    for _ in 0..3 {
+        f();
+    }
+    let mut times: Vec<Duration> = (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    println!(
+        "{name:<28} min {:>10.3?}   median {:>10.3?}",
+        times[0],
+        times[SAMPLES / 2]
+    );
+}
+
+/// A single statement printing `terms` additions, kept shallow so it stays
+/// within the compiler's nesting limit and the VM's stack.
+fn long_expression(terms: usize) -> String {
+    let mut source = String::from("print 0");
+    for i in 0..terms {
+        source.push_str(&format!(" + {i}.5 * (2 - -1) / 3\n"));
+    }
+    source.push(';');
+    source
+}
+
+fn main() {
+    let large = long_expression(20_000);
+    // The constant table holds 256 entries, so runtime benchmarks use a
+    // chunk small enough to compile.
+    let hot = long_expression(60);
+    let chunk = Compiler::new(&hot)
+        .compile()
+        .expect("benchmark must compile");
+
+    bench("scan 20k lines", || {
+        let mut scanner = Scanner::new(black_box(&large));
+        while scanner.scan_token().kind != TokenKind::Eof {}
+    });
+
+    let keywords = "and class else false for fun if nil or print return super this true var while \
+                    andy classy elsewhere f fo th tr v w\n"
+        .repeat(2_000);
+    bench("scan keywords 2k lines", || {
+        let mut scanner = Scanner::new(black_box(&keywords));
+        while scanner.scan_token().kind != TokenKind::Eof {}
+    });
+
+    bench("compile 60 lines", || {
+        black_box(Compiler::new(black_box(&hot)).compile());
+    });
+
+    let mut vm = VM::new();
+    vm.set_output(Box::new(io::sink()));
+    bench("run 60 lines x 1000", || {
+        for _ in 0..1000 {
+            black_box(vm.run(black_box(&chunk)));
+        }
+    });
+
+    let verified = chunk.verify(256).expect("benchmark must verify");
+    bench("run verified 60 lines x 1000", || {
+        for _ in 0..1000 {
+            black_box(vm.run_verified(black_box(&verified)));
+        }
+    });
+}
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rlox::compiler::Compiler;
+
+fuzz_target!(|source: &str| {
+    let _ = Compiler::new(source).compile();
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rlox::scanner::Scanner;
+use rlox::token::TokenKind;
+
+fuzz_target!(|source: &str| {
+    let mut scanner = Scanner::new(source);
+    while scanner.scan_token().kind != TokenKind::Eof {}
+});
@@ -0,0 +1,33 @@
+#![no_main]
+
+use std::io;
+
+use libfuzzer_sys::fuzz_target;
+use rlox::chunk::Chunk;
+use rlox::value::Value;
+use rlox::vm::VM;
+
+// The first byte picks how many of the following 8-byte groups become
+// constants; the rest is bytecode.
+fuzz_target!(|data: &[u8]| {
+    let Some((&count, rest)) = data.split_first() else {
+        return;
+    };
+    let mut chunk = Chunk::new();
+    let mut constants = rest.chunks_exact(8);
+    for bytes in constants.by_ref().take(count as usize % 8) {
+        chunk.add_constant(Value::Number(f64::from_le_bytes(bytes.try_into().unwrap())));
+    }
+    let code = &rest[(count as usize % 8).min(rest.len() / 8) * 8..];
+    for &byte in code {
+        chunk.write(byte, 1);
+    }
+
+    let mut vm = VM::new();
+    vm.set_output(Box::new(io::sink()));
+    vm.run(&chunk);
+    // Exercises the unchecked paths when built with `unsafe-fast`.
+    if let Ok(verified) = chunk.verify(256) {
+        vm.run_verified(&verified);
+    }
+});
@@ -0,0 +1,36 @@
+#ifndef RLOX_H
+#define RLOX_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status returned when the arguments themselves are unusable. */
+#define RLOX_INVALID_ARGUMENT (-1)
+
+typedef struct RloxVM RloxVM;
+
+RloxVM *rlox_vm_new(void);
+
+/*
+ * Compiles and runs the NUL-terminated UTF-8 `source` on `vm`. Returns
+ * 0 on success, 65 on a compile error, 70 on a runtime error, 130 if the
+ * run was interrupted, or RLOX_INVALID_ARGUMENT if `vm` or `source` is
+ * null or `source` is not UTF-8.
+ */
+int rlox_interpret(RloxVM *vm, const char *source);
+
+/*
+ * The errors from the last rlox_interpret call on `vm`, one per line, or
+ * NULL if it succeeded. The string is owned by `vm`: do not free it, and
+ * do not use it after the next rlox_interpret or rlox_vm_free on `vm`.
+ */
+const char *rlox_last_error(const RloxVM *vm);
+
+void rlox_vm_free(RloxVM *vm);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,549 @@
+//! Expression trees for tooling.
+//!
+//! The bytecode compiler stays single-pass; this module builds an explicit
+//! tree from the same token stream so that printers, interpreters, and other
+//! tools have something to walk.
+
+use std::fmt;
+
+use crate::compiler::MAX_NESTING;
+use crate::error::Diagnostic;
+use crate::scanner::Scanner;
+use crate::token::{Token, TokenKind};
+use crate::value::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Grouping(Box<Expr>),
+    Unary {
+        operator: Token,
+        right: Box<Expr>,
+    },
+    Binary {
+        left: Box<Expr>,
+        operator: Token,
+        right: Box<Expr>,
+    },
+    /// `and` or `or`, which only evaluate `right` when they must.
+    Logical {
+        left: Box<Expr>,
+        operator: Token,
+        right: Box<Expr>,
+    },
+}
+
+impl Expr {
+    /// The binary and logical expressions down the left operands from this
+    /// one, outermost first, and the operand at the bottom. Left-associative
+    /// chains such as `1 + 2 + 3` nest this way to any length, so code that
+    /// walks a tree should loop over the spine rather than recurse down it.
+    pub fn left_spine(&self) -> (Vec<&Expr>, &Expr) {
+        let mut spine = Vec::new();
+        let mut bottom = self;
+        while let Expr::Binary { left, .. } | Expr::Logical { left, .. } = bottom {
+            spine.push(bottom);
+            bottom = left;
+        }
+        (spine, bottom)
+    }
+
+    /// Moves this expression's children that have children of their own
+    /// into `out`, leaving literals in their place.
+    fn take_children(&mut self, out: &mut Vec<Expr>) {
+        let mut take = |child: &mut Box<Expr>| {
+            if !matches!(**child, Expr::Literal(_)) {
+                out.push(std::mem::replace(&mut **child, Expr::Literal(Value::Nil)));
+            }
+        };
+        match self {
+            Expr::Literal(_) => {}
+            Expr::Grouping(expr) | Expr::Unary { right: expr, .. } => take(expr),
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                take(left);
+                take(right);
+            }
+        }
+    }
+}
+
+/// Drops the tree from a worklist, since dropping a long chain recursively
+/// would overflow the stack.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.take_children(&mut pending);
+        while let Some(mut expr) = pending.pop() {
+            expr.take_children(&mut pending);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Expression(Expr),
+    Print(Expr),
+    If {
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+}
+
+/// Prints the tree in prefix form, e.g. `(+ 1.0 (group (* 2.0 3.0)))`.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(Value::Number(n)) => {
+                if *n == n.trunc() {
+                    write!(f, "{n}.0")
+                } else {
+                    write!(f, "{n}")
+                }
+            }
+            Expr::Literal(value) => match value.as_str() {
+                // Quoted, so `"nil"` and `nil` print differently.
+                Some(s) => write!(f, "{s:?}"),
+                None => write!(f, "{value}"),
+            },
+            Expr::Grouping(expr) => write!(f, "(group {expr})"),
+            Expr::Unary { operator, right } => write!(f, "({} {right})", operator.lexeme()),
+            Expr::Binary { .. } | Expr::Logical { .. } => {
+                let (spine, bottom) = self.left_spine();
+                for expr in &spine {
+                    if let Expr::Binary { operator, .. } | Expr::Logical { operator, .. } = expr {
+                        write!(f, "({} ", operator.lexeme())?;
+                    }
+                }
+                write!(f, "{bottom}")?;
+                for expr in spine.iter().rev() {
+                    if let Expr::Binary { right, .. } | Expr::Logical { right, .. } = expr {
+                        write!(f, " {right})")?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Prints the statement in the same prefix form as [`Expr`], e.g.
+/// `(print (+ 1.0 2.0))`, or `(; 1.0)` for an expression statement.
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stmt::Expression(expr) => write!(f, "(; {expr})"),
+            Stmt::Print(expr) => write!(f, "(print {expr})"),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                write!(f, "(if {condition} {then_branch}")?;
+                if let Some(else_branch) = else_branch {
+                    write!(f, " {else_branch}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Parses `source` as a script: a sequence of statements.
+pub fn parse(source: &str) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+    let mut parser = Parser::new(source);
+    let mut statements = Vec::new();
+    while !parser.check(&[TokenKind::Eof]) {
+        match parser.statement() {
+            Some(statement) => statements.push(statement),
+            None => parser.synchronize(),
+        }
+    }
+    if parser.errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(parser.errors)
+    }
+}
+
+/// Parses `source` as a single expression.
+pub fn parse_expression(source: &str) -> Result<Expr, Vec<Diagnostic>> {
+    let mut parser = Parser::new(source);
+    let expr = parser.expression();
+    if parser.errors.is_empty() {
+        parser.consume(TokenKind::Eof, "Expect end of expression.");
+    }
+    match expr {
+        Some(expr) if parser.errors.is_empty() => Ok(expr),
+        _ => Err(parser.errors),
+    }
+}
+
+struct Parser {
+    scanner: Scanner,
+    current: Token,
+    previous: Token,
+    errors: Vec<Diagnostic>,
+    depth: usize,
+}
+
+impl Parser {
+    fn new(source: &str) -> Self {
+        let mut parser = Self {
+            scanner: Scanner::new(source),
+            current: Token::new(TokenKind::Eof, 0),
+            previous: Token::new(TokenKind::Eof, 0),
+            errors: Vec::new(),
+            depth: 0,
+        };
+        parser.advance();
+        parser
+    }
+
+    /// Moves to the next token, reporting any the scanner could not make
+    /// sense of along the way.
+    fn advance(&mut self) {
+        std::mem::swap(&mut self.previous, &mut self.current);
+        loop {
+            self.current = self.scanner.scan_token();
+            let TokenKind::Error(message) = &self.current.kind else {
+                break;
+            };
+            let message = message.clone();
+            self.error_at_current(&message);
+        }
+    }
+
+    fn check(&self, kinds: &[TokenKind]) -> bool {
+        kinds.contains(&self.current.kind)
+    }
+
+    fn consume(&mut self, kind: TokenKind, message: &str) -> Option<()> {
+        if self.current.kind == kind {
+            self.advance();
+            Some(())
+        } else {
+            self.error_at_current(message);
+            None
+        }
+    }
+
+    fn error_at_current(&mut self, message: &str) {
+        self.error_at(self.current.clone(), message);
+    }
+
+    fn error_at(&mut self, token: Token, message: &str) {
+        self.errors.push(Diagnostic::at(&token, message));
+    }
+
+    /// Skips to what looks like the start of the next statement after an
+    /// error, so one mistake does not bury the errors after it.
+    fn synchronize(&mut self) {
+        self.advance();
+        while self.current.kind != TokenKind::Eof {
+            if self.previous.kind == TokenKind::Semicolon
+                || self.check(&[TokenKind::If, TokenKind::Print])
+            {
+                return;
+            }
+            self.advance();
+        }
+    }
+
+    fn statement(&mut self) -> Option<Stmt> {
+        if self.check(&[TokenKind::Print]) {
+            self.advance();
+            let expr = self.expression()?;
+            self.consume(TokenKind::Semicolon, "Expect ';' after value.")?;
+            return Some(Stmt::Print(expr));
+        }
+        if self.check(&[TokenKind::If]) {
+            self.advance();
+            return self.if_statement();
+        }
+        let expr = self.expression()?;
+        self.consume(TokenKind::Semicolon, "Expect ';' after expression.")?;
+        Some(Stmt::Expression(expr))
+    }
+
+    fn if_statement(&mut self) -> Option<Stmt> {
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenKind::RightParen, "Expect ')' after condition.")?;
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.check(&[TokenKind::Else]) {
+            self.advance();
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+        Some(Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    fn expression(&mut self) -> Option<Expr> {
+        self.binary(0)
+    }
+
+    /// Binary operators that bind at least as tightly as `min_level` (see
+    /// [`binary_level`]), left-associatively. Climbing precedence in a loop
+    /// rather than descending one function per level keeps each nested
+    /// grouping to a handful of stack frames, so `MAX_NESTING` of them fit
+    /// on a small thread stack.
+    ///
+    /// Comma-separated lists must parse their items with `binary(1)`, so the
+    /// comma is left for them to consume.
+    fn binary(&mut self, min_level: u8) -> Option<Expr> {
+        let mut expr = self.unary()?;
+        while let Some(level) = binary_level(&self.current.kind).filter(|&l| l >= min_level) {
+            self.advance();
+            let operator = self.previous.clone();
+            let right = Box::new(self.binary(level + 1)?);
+            let left = Box::new(expr);
+            expr = match operator.kind {
+                TokenKind::And | TokenKind::Or => Expr::Logical {
+                    left,
+                    operator,
+                    right,
+                },
+                _ => Expr::Binary {
+                    left,
+                    operator,
+                    right,
+                },
+            };
+        }
+        Some(expr)
+    }
+
+    fn unary(&mut self) -> Option<Expr> {
+        if self.depth >= MAX_NESTING {
+            self.error_at_current("Expression nesting too deep.");
+            return None;
+        }
+        self.depth += 1;
+        let expr = self.unary_operand();
+        self.depth -= 1;
+        expr
+    }
+
+    fn unary_operand(&mut self) -> Option<Expr> {
+        if self.check(&[TokenKind::Bang, TokenKind::Minus]) {
+            self.advance();
+            let operator = self.previous.clone();
+            let right = self.unary()?;
+            return Some(Expr::Unary {
+                operator,
+                right: Box::new(right),
+            });
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Option<Expr> {
+        match self.current.kind.clone() {
+            TokenKind::Number(s) => {
+                self.advance();
+                match s.parse() {
+                    Ok(n) => Some(Expr::Literal(Value::Number(n))),
+                    Err(_) => {
+                        self.error_at(self.previous.clone(), "Invalid number.");
+                        None
+                    }
+                }
+            }
+            TokenKind::String(s) => {
+                self.advance();
+                // The token keeps its quotes.
+                Some(Expr::Literal(Value::string(&s[1..s.len() - 1])))
+            }
+            TokenKind::Nil => {
+                self.advance();
+                Some(Expr::Literal(Value::Nil))
+            }
+            TokenKind::True => {
+                self.advance();
+                Some(Expr::Literal(Value::Bool(true)))
+            }
+            TokenKind::False => {
+                self.advance();
+                Some(Expr::Literal(Value::Bool(false)))
+            }
+            TokenKind::LeftParen => {
+                self.advance();
+                let expr = self.expression()?;
+                self.consume(TokenKind::RightParen, "Expect ')' after expression.")?;
+                Some(Expr::Grouping(Box::new(expr)))
+            }
+            _ => {
+                self.error_at_current("Expect expression.");
+                None
+            }
+        }
+    }
+}
+
+/// How tightly a binary operator binds, from the comma at 0 up to factors,
+/// or `None` if `kind` is not one.
+fn binary_level(kind: &TokenKind) -> Option<u8> {
+    match kind {
+        TokenKind::Comma => Some(0),
+        TokenKind::Or => Some(1),
+        TokenKind::And => Some(2),
+        TokenKind::BangEqual | TokenKind::EqualEqual => Some(3),
+        TokenKind::Greater | TokenKind::GreaterEqual | TokenKind::Less | TokenKind::LessEqual => {
+            Some(4)
+        }
+        TokenKind::Minus | TokenKind::Plus => Some(5),
+        TokenKind::Slash | TokenKind::Star => Some(6),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_literals() {
+        let expr = parse_expression(r#""nil" + nil + "a\b\u{a}""#).unwrap();
+        assert_eq!(expr.to_string(), r#"(+ (+ "nil" nil) "a\\b\n")"#);
+    }
+
+    #[test]
+    fn precedence() {
+        let expr = parse_expression("1 + 2 * 3 - 4 / 5").unwrap();
+        assert_eq!(expr.to_string(), "(- (+ 1.0 (* 2.0 3.0)) (/ 4.0 5.0))");
+    }
+
+    #[test]
+    fn grouping_and_unary() {
+        let expr = parse_expression("-(1.5 + --2)").unwrap();
+        assert_eq!(expr.to_string(), "(- (group (+ 1.5 (- (- 2.0)))))");
+    }
+
+    #[test]
+    fn literals_and_not() {
+        let expr = parse_expression("!true == !!nil").unwrap();
+        assert_eq!(expr.to_string(), "(== (! true) (! (! nil)))");
+    }
+
+    #[test]
+    fn comparison_binds_tighter_than_equality() {
+        let expr = parse_expression("1 < 2 == 3 >= 4 + 5").unwrap();
+        assert_eq!(expr.to_string(), "(== (< 1.0 2.0) (>= 3.0 (+ 4.0 5.0)))");
+    }
+
+    #[test]
+    fn long_chains() {
+        let expr = parse_expression("1 - 2 - 3").unwrap();
+        assert_eq!(expr.to_string(), "(- (- 1.0 2.0) 3.0)");
+        let expr = parse_expression(&format!("1{}", " + 1".repeat(30_000))).unwrap();
+        let printed = expr.to_string();
+        assert!(printed.starts_with("(+ (+ "));
+        assert!(printed.ends_with(" 1.0) 1.0)"));
+        drop(expr);
+    }
+
+    #[test]
+    fn comma() {
+        let expr = parse_expression("1, 2 + 3, 4").unwrap();
+        assert_eq!(expr.to_string(), "(, (, 1.0 (+ 2.0 3.0)) 4.0)");
+    }
+
+    #[test]
+    fn logical_operators() {
+        let expr = parse_expression("1 or 2 and 3 == 4, 5").unwrap();
+        assert_eq!(expr.to_string(), "(, (or 1.0 (and 2.0 (== 3.0 4.0))) 5.0)");
+    }
+
+    #[test]
+    fn if_statements() {
+        let statements = parse(
+            "if (true) print 1; else if (nil) 2;
+if (false) 3;",
+        )
+        .unwrap();
+        let printed: Vec<String> = statements.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            printed,
+            [
+                "(if true (print 1.0) (if nil (; 2.0)))",
+                "(if false (; 3.0))"
+            ]
+        );
+        let errors: Vec<String> = parse(
+            "if 1) print 2;
+if (1 print 3;",
+        )
+        .unwrap_err()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        assert_eq!(
+            errors,
+            [
+                "[line 1] Error '1': Expect '(' after 'if'.",
+                "[line 2] Error 'print': Expect ')' after condition.",
+            ]
+        );
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse_expression(&nested(MAX_NESTING - 1)).is_ok());
+        assert_eq!(
+            error_messages(&nested(MAX_NESTING)),
+            vec!["[line 1] Error '1': Expression nesting too deep."]
+        );
+    }
+
+    #[test]
+    fn statements() {
+        let statements = parse("print 1 + 2;\n3;").unwrap();
+        let printed: Vec<String> = statements.iter().map(ToString::to_string).collect();
+        assert_eq!(printed, ["(print (+ 1.0 2.0))", "(; 3.0)"]);
+        assert_eq!(parse(""), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn statement_errors_are_recovered_from() {
+        let errors: Vec<String> = parse("print 1;\nprint (;\n2;\n3")
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "[line 2] Error ';': Expect expression.",
+                "[line 4] Error '': Expect ';' after expression.",
+            ]
+        );
+    }
+
+    fn error_messages(source: &str) -> Vec<String> {
+        let errors = parse_expression(source).unwrap_err();
+        errors.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            error_messages("(1 + 2"),
+            vec!["[line 1] Error '': Expect ')' after expression."]
+        );
+        assert_eq!(
+            error_messages("1 2"),
+            vec!["[line 1] Error '2': Expect end of expression."]
+        );
+        assert_eq!(
+            error_messages("\n*"),
+            vec!["[line 2] Error '*': Expect expression."]
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! What the ahead-of-time backends in [`c`](crate::c) and
+//! [`wasm`](crate::wasm) have in common: both only know how to lower
+//! straight-line arithmetic on numbers.
+
+use crate::chunk::Chunk;
+use crate::opcode::OpCode;
+
+/// Checks that `chunk` is valid bytecode the backend called `backend` can
+/// translate, or explains why not.
+pub(crate) fn check_supported(chunk: &Chunk, backend: &str) -> Result<(), String> {
+    chunk.verify(usize::MAX)?;
+    if let Some(value) = chunk.constants.iter().find(|v| v.as_number().is_none()) {
+        return Err(format!(
+            "The {backend} backend only supports numbers, not {}.",
+            value.type_name()
+        ));
+    }
+    if chunk
+        .bytecode_stats()
+        .opcodes
+        .iter()
+        .any(|(op, _)| op.is_jump())
+    {
+        return Err(format!(
+            "The {backend} backend does not support control flow yet."
+        ));
+    }
+    if let Some(op) = first_non_number(chunk) {
+        return Err(format!(
+            "The {backend} backend only supports numbers, not {op}."
+        ));
+    }
+    Ok(())
+}
+
+/// The first instruction that leaves something other than a number, the
+/// only type the generated code has a representation for. The `nil` a
+/// script returns is fine, since nothing reads it.
+fn first_non_number(chunk: &Chunk) -> Option<OpCode> {
+    let mut offset = 0;
+    while let Some(&byte) = chunk.code.get(offset) {
+        let op = OpCode::try_from(byte).expect("chunk was verified");
+        offset += op.size();
+        let returned = chunk.code.get(offset) == Some(&OpCode::Return.into());
+        match op {
+            OpCode::Nil if returned => {}
+            OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::Equal
+            | OpCode::Greater
+            | OpCode::Less
+            | OpCode::Not => return Some(op),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+
+    #[test]
+    fn names_the_backend() {
+        let check = |source| check_supported(&Compiler::new(source).compile().unwrap(), "C");
+        assert_eq!(check("print -(1 + 2) * 3;"), Ok(()));
+        assert_eq!(
+            check("print !1;"),
+            Err("The C backend only supports numbers, not OP_NOT.".to_owned())
+        );
+        assert_eq!(
+            check("if (1) 2;"),
+            Err("The C backend does not support control flow yet.".to_owned())
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Translates a chunk to portable C.
+//!
+//! Each Lox function becomes one C function, with a local per stack slot in
+//! place of the VM's stack. The small runtime it needs is emitted at the
+//! top of the file, so the output builds on its own with `cc script.c -lm`.
+
+use std::fmt::Write;
+
+use crate::backend;
+use crate::chunk::Chunk;
+use crate::opcode::OpCode;
+
+/// Prints numbers exactly as the VM does.
+const RUNTIME: &str = "\
+#include <math.h>
+#include <stdio.h>
+
+static void lox_print(double value) {
+  if (isnan(value)) {
+    puts(\"nan\");
+  } else {
+    printf(\"%g\\n\", value);
+  }
+}
+";
+
+/// Translates `chunk` into a C translation unit, or explains why the chunk
+/// is not bytecode the backend can translate.
+pub fn compile(chunk: &Chunk) -> Result<String, String> {
+    backend::check_supported(chunk, "C")?;
+
+    let mut out = String::from("/* Generated by rlox. */\n");
+    out.push_str(RUNTIME);
+    out.push_str("\nstatic void lox_script(void) {\n");
+    let mut body = String::new();
+    let slots = translate(chunk, &mut body).expect("writing to a String cannot fail");
+    let names: Vec<String> = (0..slots).map(|slot| format!("s{slot}")).collect();
+    writeln!(out, "  double {};", names.join(", ")).expect("writing to a String cannot fail");
+    out.push_str(&body);
+    out.push_str("}\n\nint main(void) {\n  lox_script();\n  return 0;\n}\n");
+    Ok(out)
+}
+
+/// Translates instructions up to the first return, which `verify` has
+/// guaranteed exists. Slot `sN` holds stack entry `N`; returns how many
+/// slots the code uses.
+fn translate(chunk: &Chunk, out: &mut String) -> Result<usize, std::fmt::Error> {
+    let mut offset = 0;
+    let mut depth: usize = 0;
+    let mut slots = 0;
+    loop {
+        let op = OpCode::try_from(chunk.code[offset]).expect("chunk was verified");
+        offset += 1;
+        match op {
+            OpCode::Constant => {
+                let value = chunk.constants[chunk.code[offset] as usize]
+                    .as_number()
+                    .expect("constants were checked");
+                offset += 1;
+                writeln!(out, "  s{depth} = {};", literal(value))?;
+            }
+            OpCode::Add | OpCode::Subtract | OpCode::Multiply | OpCode::Divide => {
+                let operator = match op {
+                    OpCode::Add => '+',
+                    OpCode::Subtract => '-',
+                    OpCode::Multiply => '*',
+                    _ => '/',
+                };
+                let (a, b) = (depth - 2, depth - 1);
+                writeln!(out, "  s{a} = s{a} {operator} s{b};")?;
+            }
+            OpCode::Negate => writeln!(out, "  s{0} = -s{0};", depth - 1)?,
+            // Only ever the script's return value, which goes unused.
+            OpCode::Nil => {}
+            OpCode::True
+            | OpCode::False
+            | OpCode::Equal
+            | OpCode::Greater
+            | OpCode::Less
+            | OpCode::Not
+            | OpCode::Jump
+            | OpCode::JumpIfFalse => unreachable!("rejected by compile"),
+            OpCode::Pop => {}
+            OpCode::Print => writeln!(out, "  lox_print(s{});", depth - 1)?,
+            OpCode::Return => return Ok(slots),
+        }
+        depth = depth.saturating_add_signed(op.stack_effect());
+        slots = slots.max(depth);
+    }
+}
+
+/// A C expression for `value` that reads back as the same double.
+fn literal(value: f64) -> String {
+    if value.is_nan() {
+        "NAN".to_owned()
+    } else if value.is_infinite() {
+        if value > 0.0 {
+            "INFINITY"
+        } else {
+            "(-INFINITY)"
+        }
+        .to_owned()
+    } else if value.is_sign_negative() {
+        format!("({value:e})")
+    } else {
+        format!("{value:e}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+
+    #[test]
+    fn translates_expression() {
+        let chunk = Compiler::new("print -(1 + 2.5) * 3, 4;").compile().unwrap();
+        let source = compile(&chunk).unwrap();
+        let body = source
+            .split("static void lox_script(void) {\n")
+            .nth(1)
+            .unwrap();
+        assert!(body.starts_with(
+            "  double s0, s1;\n\
+             \x20 s0 = 1e0;\n\
+             \x20 s1 = 2.5e0;\n\
+             \x20 s0 = s0 + s1;\n\
+             \x20 s0 = -s0;\n\
+             \x20 s1 = 3e0;\n\
+             \x20 s0 = s0 * s1;\n\
+             \x20 s0 = 4e0;\n\
+             \x20 lox_print(s0);\n}\n"
+        ));
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(literal(0.1), "1e-1");
+        assert_eq!(literal(-0.0), "(-0e0)");
+        assert_eq!(literal(f64::INFINITY), "INFINITY");
+        assert_eq!(literal(f64::NAN), "NAN");
+    }
+}
@@ -0,0 +1,136 @@
+//! An on-disk cache of compiled chunks, keyed by a hash of the source and
+//! the build that compiled it, so unchanged scripts skip scanning and
+//! compiling.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
+
+use crate::chunk::{Chunk, FORMAT_VERSION};
+
+pub struct ChunkCache {
+    dir: PathBuf,
+}
+
+impl ChunkCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `$XDG_CACHE_HOME/rlox`, falling back to `~/.cache/rlox`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let base = env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(base.join("rlox"))
+    }
+
+    /// The cached chunk for `source`, if there is a readable one.
+    pub fn load(&self, source: &str) -> Option<Chunk> {
+        let bytes = fs::read(self.path(source)).ok()?;
+        Chunk::from_bytes(&bytes).ok()
+    }
+
+    pub fn store(&self, source: &str, chunk: &Chunk) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file and rename it into place, so a
+        // concurrent `load` never sees a partial chunk.
+        let path = self.path(source);
+        let temp = path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&temp, chunk.to_bytes())?;
+        fs::rename(temp, path)
+    }
+
+    fn path(&self, source: &str) -> PathBuf {
+        let key = fnv1a(&[
+            env!("CARGO_PKG_VERSION").as_bytes(),
+            &[0, FORMAT_VERSION],
+            &build_fingerprint().to_le_bytes(),
+            source.as_bytes(),
+        ]);
+        self.dir
+            .join(format!("{key:016x}-{:x}.rloxc", source.len()))
+    }
+}
+
+/// Identifies the running executable by its size and modification time, so
+/// a rebuilt compiler does not pick up chunks from an older one that had
+/// the same version. 0 if the executable cannot be inspected.
+fn build_fingerprint() -> u64 {
+    static FINGERPRINT: OnceLock<u64> = OnceLock::new();
+    *FINGERPRINT.get_or_init(|| {
+        let Some(metadata) = env::current_exe().and_then(fs::metadata).ok() else {
+            return 0;
+        };
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos());
+        fnv1a(&[&metadata.len().to_le_bytes(), &modified.to_le_bytes()])
+    })
+}
+
+/// 64-bit FNV-1a over the concatenation of `parts`.
+fn fnv1a(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in parts.iter().flat_map(|part| part.iter()) {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+
+    fn temp_cache(name: &str) -> ChunkCache {
+        let dir = env::temp_dir().join(format!("rlox-cache-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        ChunkCache::new(dir)
+    }
+
+    #[test]
+    fn fnv1a_reference_values() {
+        assert_eq!(fnv1a(&[b""]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(&[b"a"]), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(&[b"foo", b"bar"]), fnv1a(&[b"foobar"]));
+    }
+
+    #[test]
+    fn build_fingerprint_is_stable() {
+        assert_ne!(build_fingerprint(), 0);
+        assert_eq!(build_fingerprint(), build_fingerprint());
+    }
+
+    #[test]
+    fn stores_and_loads() {
+        let cache = temp_cache("round-trip");
+        let source = "print 1 + 2;";
+        assert!(cache.load(source).is_none());
+
+        let chunk = Compiler::new(source).compile().unwrap();
+        cache.store(source, &chunk).unwrap();
+        let loaded = cache.load(source).unwrap();
+        assert_eq!(loaded.code, chunk.code);
+        assert_eq!(loaded.constants, chunk.constants);
+        assert!(cache.load("print 1 + 3;").is_none());
+
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn ignores_corrupt_entries() {
+        let cache = temp_cache("corrupt");
+        fs::create_dir_all(&cache.dir).unwrap();
+        fs::write(cache.path("1"), b"garbage").unwrap();
+        assert!(cache.load("1").is_none());
+
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
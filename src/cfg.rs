@@ -0,0 +1,197 @@
+//! Control-flow graphs of compiled chunks.
+//!
+//! A chunk's code is split into basic blocks: straight-line runs of
+//! instructions that are only entered at the top and only left at the
+//! bottom. A block starts at the beginning of the code, at a jump target,
+//! or after a jump or return, and code that nothing reaches still gets a
+//! block of its own, with no way in.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::chunk::Chunk;
+use crate::opcode::OpCode;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    /// Offset of the block's first instruction.
+    pub start: usize,
+    /// Offset just past the block's last instruction.
+    pub end: usize,
+    /// Indices of the blocks control can pass to from this one.
+    pub successors: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+    /// In code order, so the entry block is first.
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl Cfg {
+    /// Splits `chunk` into blocks. Fails on bytes that do not decode as
+    /// instructions and on jumps that do not land on one.
+    pub fn build(chunk: &Chunk) -> Result<Self, String> {
+        let len = chunk.code.len();
+        let mut instructions = Vec::new();
+        let mut leaders = BTreeSet::from([0]);
+        let mut offset = 0;
+        while let Some(&byte) = chunk.code.get(offset) {
+            let op = OpCode::try_from(byte)
+                .map_err(|_| format!("Invalid opcode {byte} at {offset}."))?;
+            instructions.push((offset, op));
+            offset += op.size();
+            if offset > len {
+                return Err("Unexpected end of bytecode.".to_owned());
+            }
+            if let Some(target) = chunk.jump_target(offset - op.size()) {
+                leaders.insert(target);
+            }
+            if op.is_jump() || op.is_terminator() {
+                leaders.insert(offset);
+            }
+        }
+        for &leader in &leaders {
+            let starts_instruction = instructions
+                .binary_search_by_key(&leader, |&(offset, _)| offset)
+                .is_ok();
+            if !starts_instruction && leader != len {
+                return Err(format!(
+                    "Jump into the middle of an instruction at {leader}."
+                ));
+            }
+        }
+
+        let starts: Vec<usize> = leaders.into_iter().filter(|&start| start < len).collect();
+        let block_at = |offset| starts.binary_search(&offset).ok();
+        let mut blocks = Vec::new();
+        for (index, &start) in starts.iter().enumerate() {
+            let end = starts.get(index + 1).copied().unwrap_or(len);
+            let (last, op) = instructions
+                .iter()
+                .rev()
+                .find(|&&(offset, _)| offset < end)
+                .copied()
+                .expect("every block has an instruction");
+            let mut successors = Vec::new();
+            if !op.is_terminator() {
+                successors.extend(block_at(end));
+            }
+            if let Some(target) = chunk.jump_target(last).and_then(block_at)
+                && !successors.contains(&target)
+            {
+                successors.push(target);
+            }
+            blocks.push(BasicBlock {
+                start,
+                end,
+                successors,
+            });
+        }
+        Ok(Self { blocks })
+    }
+
+    /// The graph in Graphviz DOT format, one box per block listing its
+    /// disassembled instructions.
+    pub fn to_dot(&self, chunk: &Chunk) -> String {
+        let name = chunk.name.as_deref().unwrap_or("script");
+        let mut out = format!("digraph \"{}\" {{\n", escape(name));
+        out.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        for (index, block) in self.blocks.iter().enumerate() {
+            let mut listing = String::new();
+            let mut offset = block.start;
+            while offset < block.end {
+                offset = chunk
+                    .write_instruction(&mut listing, offset)
+                    .expect("writing to a String cannot fail");
+            }
+            // Left-justify every line, the way a disassembly reads.
+            let label = escape(&listing).replace('\n', "\\l");
+            writeln!(out, "    b{index} [label=\"{label}\"];").unwrap();
+        }
+        for (index, block) in self.blocks.iter().enumerate() {
+            for successor in &block.successors {
+                writeln!(out, "    b{index} -> b{successor};").unwrap();
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::value::Value;
+
+    #[test]
+    fn single_block() {
+        let chunk = Compiler::new("print 1 + 2;").compile().unwrap();
+        let cfg = Cfg::build(&chunk).unwrap();
+        assert_eq!(
+            cfg.blocks,
+            [BasicBlock {
+                start: 0,
+                end: chunk.code.len(),
+                successors: Vec::new(),
+            }]
+        );
+        assert_eq!(
+            cfg.to_dot(&chunk),
+            "digraph \"script\" {\n    node [shape=box, fontname=\"monospace\"];\n    \
+             b0 [label=\"0000    1 OP_CONSTANT         0 '1'\\l\
+             0002    | OP_CONSTANT         1 '2'\\l\
+             0004    | OP_ADD\\l\
+             0005    | OP_PRINT\\l\
+             0006    | OP_NIL\\l\
+             0007    | OP_RETURN\\l\"];\n}\n"
+        );
+    }
+
+    #[test]
+    fn code_after_return_is_unreachable() {
+        let mut chunk = Chunk::new();
+        chunk.add_constant(Value::Number(1.0));
+        for byte in [OpCode::Constant as u8, 0, OpCode::Return as u8] {
+            chunk.write(byte, 1);
+        }
+        chunk.write(OpCode::Pop as u8, 2);
+        chunk.write(OpCode::Negate as u8, 2);
+        let blocks = Cfg::build(&chunk).unwrap().blocks;
+        assert_eq!(blocks.len(), 2);
+        assert_eq!((blocks[1].start, blocks[1].end), (3, 5));
+        assert!(blocks.iter().all(|block| block.successors.is_empty()));
+    }
+
+    #[test]
+    fn branches() {
+        let chunk = Compiler::new("if (true) print 1; else print 2;")
+            .compile()
+            .unwrap();
+        let cfg = Cfg::build(&chunk).unwrap();
+        let edges: Vec<&[usize]> = cfg
+            .blocks
+            .iter()
+            .map(|block| block.successors.as_slice())
+            .collect();
+        assert_eq!(edges, [&[1, 2][..], &[3], &[3], &[]]);
+        let dot = cfg.to_dot(&chunk);
+        for edge in ["b0 -> b1;", "b0 -> b2;", "b1 -> b3;", "b2 -> b3;"] {
+            assert!(dot.contains(edge), "{edge} missing from {dot}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_code() {
+        let mut chunk = Chunk::new();
+        chunk.write(OpCode::Constant as u8, 1);
+        assert!(Cfg::build(&chunk).is_err());
+        chunk.code[0] = 0xff;
+        assert!(Cfg::build(&chunk).is_err());
+    }
+}
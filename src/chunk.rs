@@ -1,21 +1,151 @@
-use crate::{opcode::OpCode, value::Value};
+use std::fmt;
+
+use crate::opcode::OpCode;
+use crate::source_map::SourceId;
+use crate::value::{Obj, Value};
+
+/// Marks serialized chunks, followed by a format version byte.
+const MAGIC: &[u8] = b"rloxc";
+/// The version of the serialized format, bumped whenever it changes.
+pub const FORMAT_VERSION: u8 = 8;
+
+/// Serialized constants start with one of these, then their payload.
+const TAG_NIL: u8 = 0;
+const TAG_BOOL: u8 = 1;
+const TAG_NUMBER: u8 = 2;
+const TAG_STRING: u8 = 3;
 
 #[derive(Debug, Clone)]
 pub struct Chunk {
-    pub code: Vec<(u8, usize)>,
+    /// The function the code belongs to, or `None` for top-level script
+    /// code.
+    pub name: Option<String>,
+    /// The file the code was compiled from, if the compiler was told. Not
+    /// serialized, since ids belong to one [`SourceMap`].
+    ///
+    /// [`SourceMap`]: crate::source_map::SourceMap
+    pub source: Option<SourceId>,
+    pub code: Vec<u8>,
+    /// Source positions, run-length encoded: each entry is a line, a column
+    /// (0 if unknown), and the number of consecutive bytes of `code` that
+    /// came from there.
+    lines: Vec<(usize, usize, usize)>,
     pub constants: Vec<Value>,
+    /// The most values the code ever has on the stack at once, as computed
+    /// by the compiler.
+    pub max_stack: usize,
+}
+
+/// A chunk that passed [`Chunk::verify`], borrowed so it cannot change
+/// while the proof is held.
+#[derive(Debug, Clone, Copy)]
+pub struct VerifiedChunk<'a> {
+    chunk: &'a Chunk,
+    stack_size: usize,
+}
+
+impl<'a> VerifiedChunk<'a> {
+    pub fn chunk(&self) -> &'a Chunk {
+        self.chunk
+    }
+
+    /// The stack size the chunk was verified against.
+    pub fn stack_size(&self) -> usize {
+        self.stack_size
+    }
+}
+
+/// The size and makeup of a chunk's bytecode, from
+/// [`Chunk::bytecode_stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BytecodeStats {
+    /// The chunk's function, `script` for top-level code.
+    pub function: String,
+    pub instructions: usize,
+    pub bytes: usize,
+    pub constants: usize,
+    /// Constants per value type, most common first.
+    pub constant_types: Vec<(&'static str, usize)>,
+    pub max_stack: usize,
+    /// How often each opcode occurs, most common first. Opcodes that do
+    /// not occur are left out.
+    pub opcodes: Vec<(OpCode, usize)>,
+}
+
+/// Sorts counts most common first, keeping the original order for ties.
+fn by_count<T>(counts: &mut [(T, usize)]) {
+    counts.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+}
+
+impl fmt::Display for BytecodeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "== {} ==", self.function)?;
+        writeln!(f, "instructions: {}", self.instructions)?;
+        writeln!(f, "bytes:        {}", self.bytes)?;
+        write!(f, "constants:    {}", self.constants)?;
+        let types: Vec<String> = self
+            .constant_types
+            .iter()
+            .map(|(name, count)| format!("{name}: {count}"))
+            .collect();
+        if !types.is_empty() {
+            write!(f, " ({})", types.join(", "))?;
+        }
+        writeln!(f)?;
+        writeln!(f, "max stack:    {}", self.max_stack)?;
+        writeln!(f, "opcodes:")?;
+        for (op, count) in &self.opcodes {
+            writeln!(f, "  {op:<16} {count}")?;
+        }
+        Ok(())
+    }
 }
 
 impl Chunk {
     pub fn new() -> Self {
         Self {
+            name: None,
+            source: None,
             code: Vec::new(),
+            lines: Vec::new(),
             constants: Vec::new(),
+            max_stack: 0,
         }
     }
 
     pub fn write(&mut self, byte: u8, line: usize) {
-        self.code.push((byte, line));
+        self.write_at(byte, line, 0);
+    }
+
+    /// Like [`Chunk::write`], also recording the 1-based column of the
+    /// token the byte came from.
+    pub fn write_at(&mut self, byte: u8, line: usize, column: usize) {
+        self.code.push(byte);
+        match self.lines.last_mut() {
+            Some((last_line, last_column, count))
+                if (*last_line, *last_column) == (line, column) =>
+            {
+                *count += 1
+            }
+            _ => self.lines.push((line, column, 1)),
+        }
+    }
+
+    /// The source line of the byte at `offset`.
+    pub fn line(&self, offset: usize) -> Option<usize> {
+        self.position(offset).map(|(line, _)| line)
+    }
+
+    /// The source line and column of the byte at `offset`.
+    pub fn position(&self, offset: usize) -> Option<(usize, usize)> {
+        let mut start = 0;
+        for &(line, column, count) in &self.lines {
+            start += count;
+            if offset < start {
+                return Some((line, column));
+            }
+        }
+        None
     }
 
     pub fn add_constant(&mut self, value: Value) -> usize {
@@ -23,52 +153,316 @@ impl Chunk {
         self.constants.len() - 1
     }
 
+    /// Where the jump at `offset` lands, or `None` if there is no jump
+    /// there. Does not check that the target is in bounds.
+    pub fn jump_target(&self, offset: usize) -> Option<usize> {
+        let op = OpCode::try_from(*self.code.get(offset)?).ok()?;
+        if !op.is_jump() {
+            return None;
+        }
+        let operand = self.code.get(offset + 1..offset + 3)?;
+        Some(offset + 3 + u16::from_be_bytes([operand[0], operand[1]]) as usize)
+    }
+
+    /// Checks that every path through the chunk, from its first instruction
+    /// on an empty stack of `stack_size` slots, reaches a return without
+    /// reading a missing byte, an unknown opcode, or an invalid constant,
+    /// and without overflowing or underflowing the stack. Paths that meet
+    /// must agree on the stack depth.
+    pub fn verify(&self, stack_size: usize) -> Result<VerifiedChunk<'_>, String> {
+        let mut depths: Vec<Option<usize>> = vec![None; self.code.len()];
+        let mut pending = vec![(0, 0)];
+        while let Some((offset, depth)) = pending.pop() {
+            let Some(&byte) = self.code.get(offset) else {
+                return Err("Unexpected end of bytecode.".to_owned());
+            };
+            match depths[offset] {
+                Some(seen) if seen == depth => continue,
+                Some(_) => return Err(format!("Inconsistent stack depth at {offset}.")),
+                None => depths[offset] = Some(depth),
+            }
+            let op = OpCode::try_from(byte)
+                .map_err(|_| format!("Invalid opcode {byte} at {offset}."))?;
+            if offset + op.size() > self.code.len() {
+                return Err("Unexpected end of bytecode.".to_owned());
+            }
+            if op == OpCode::Constant {
+                let index = self.code[offset + 1];
+                if self.constants.get(index as usize).is_none() {
+                    return Err(format!("Invalid constant index {index} at {offset}."));
+                }
+            }
+            if depth < op.stack_inputs() {
+                return Err(format!("Stack underflow at {offset}."));
+            }
+            if op == OpCode::Return {
+                continue;
+            }
+            let depth = depth.saturating_add_signed(op.stack_effect());
+            if depth > stack_size {
+                return Err(format!("Stack overflow at {offset}."));
+            }
+            if let Some(target) = self.jump_target(offset) {
+                pending.push((target, depth));
+            }
+            if !op.is_terminator() {
+                pending.push((offset + op.size(), depth));
+            }
+        }
+        Ok(VerifiedChunk {
+            chunk: self,
+            stack_size,
+        })
+    }
+
+    /// Counts the chunk's instructions, constants, and opcodes. Bytes that
+    /// do not decode as an opcode are counted in `bytes` only.
+    pub fn bytecode_stats(&self) -> BytecodeStats {
+        let mut opcodes: Vec<(OpCode, usize)> = OpCode::ALL.map(|op| (op, 0)).to_vec();
+        let mut instructions = 0;
+        let mut offset = 0;
+        while let Some(&byte) = self.code.get(offset) {
+            match OpCode::try_from(byte) {
+                Ok(op) => {
+                    opcodes[byte as usize].1 += 1;
+                    instructions += 1;
+                    offset += op.size();
+                }
+                Err(()) => offset += 1,
+            }
+        }
+        opcodes.retain(|&(_, count)| count > 0);
+        by_count(&mut opcodes);
+
+        let mut constant_types: Vec<(&'static str, usize)> = Vec::new();
+        for constant in &self.constants {
+            let name = constant.type_name();
+            match constant_types.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, count)) => *count += 1,
+                None => constant_types.push((name, 1)),
+            }
+        }
+        by_count(&mut constant_types);
+
+        BytecodeStats {
+            function: self.name.clone().unwrap_or_else(|| "script".to_owned()),
+            instructions,
+            bytes: self.code.len(),
+            constants: self.constants.len(),
+            constant_types,
+            max_stack: self.max_stack,
+            opcodes,
+        }
+    }
+
+    /// Serializes the chunk in a compact little-endian format that
+    /// [`Chunk::from_bytes`] reads back.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(FORMAT_VERSION);
+        // No function is unnamed, so an empty name stands for the script.
+        let name = self.name.as_deref().unwrap_or("");
+        write_len(&mut out, name.len());
+        out.extend_from_slice(name.as_bytes());
+        write_len(&mut out, self.max_stack);
+        write_len(&mut out, self.code.len());
+        out.extend_from_slice(&self.code);
+        write_len(&mut out, self.lines.len());
+        for &(line, column, count) in &self.lines {
+            write_len(&mut out, line);
+            write_len(&mut out, column);
+            write_len(&mut out, count);
+        }
+        write_len(&mut out, self.constants.len());
+        for constant in &self.constants {
+            match constant {
+                Value::Nil => out.push(TAG_NIL),
+                Value::Bool(b) => out.extend_from_slice(&[TAG_BOOL, u8::from(*b)]),
+                Value::Number(n) => {
+                    out.push(TAG_NUMBER);
+                    out.extend_from_slice(&n.to_le_bytes());
+                }
+                Value::Obj(obj) => match &**obj {
+                    Obj::String(s) => {
+                        out.push(TAG_STRING);
+                        write_len(&mut out, s.len());
+                        out.extend_from_slice(s.as_bytes());
+                    }
+                },
+            }
+        }
+        out
+    }
+
+    /// Reads a chunk written by [`Chunk::to_bytes`]. Only the layout is
+    /// checked; use [`Chunk::verify`] before trusting the bytecode.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len())? != MAGIC || reader.take(1)? != [FORMAT_VERSION] {
+            return Err("Not a serialized chunk.".to_owned());
+        }
+        let name_len = reader.len()?;
+        let name = String::from_utf8(reader.take(name_len)?.to_vec())
+            .map_err(|_| "Invalid chunk name.".to_owned())?;
+        let name = (!name.is_empty()).then_some(name);
+        let max_stack = reader.len()?;
+        let code_len = reader.len()?;
+        let code = reader.take(code_len)?.to_vec();
+        let mut lines = Vec::new();
+        for _ in 0..reader.len()? {
+            lines.push((reader.len()?, reader.len()?, reader.len()?));
+        }
+        let mut constants = Vec::new();
+        for _ in 0..reader.len()? {
+            let constant = match reader.take(1)? {
+                [TAG_NIL] => Value::Nil,
+                [TAG_BOOL] => Value::Bool(reader.take(1)? != [0]),
+                [TAG_NUMBER] => {
+                    let bytes = reader.take(8)?.try_into().expect("took 8 bytes");
+                    Value::Number(f64::from_le_bytes(bytes))
+                }
+                [TAG_STRING] => {
+                    let len = reader.len()?;
+                    let bytes = reader.take(len)?.to_vec();
+                    let s = String::from_utf8(bytes)
+                        .map_err(|_| "Invalid string constant.".to_owned())?;
+                    Value::string(s)
+                }
+                _ => return Err("Invalid constant tag.".to_owned()),
+            };
+            constants.push(constant);
+        }
+        if !reader.0.is_empty() {
+            return Err("Trailing bytes after chunk.".to_owned());
+        }
+        Ok(Self {
+            name,
+            source: None,
+            code,
+            lines,
+            constants,
+            max_stack,
+        })
+    }
+
     pub fn disassemble(&self, name: &str) {
-        println!("== {name} ==");
+        print!("{}", self.disassembly(name));
+    }
 
+    /// The full listing `disassemble` prints, as a string.
+    pub fn disassembly(&self, name: &str) -> String {
+        let mut out = format!("== {name} ==\n");
         let mut offset = 0;
         while offset < self.code.len() {
-            offset = self.disassemble_instruction(offset);
+            offset = self
+                .write_instruction(&mut out, offset)
+                .expect("writing to a String cannot fail");
         }
+        out
     }
 
     pub fn disassemble_instruction(&self, offset: usize) -> usize {
-        print!("{offset:04} ");
-        let (byte, line) = self.code[offset];
-        if offset > 0 && line == self.code[offset - 1].1 {
-            print!("   | ");
+        let mut out = String::new();
+        let next = self
+            .write_instruction(&mut out, offset)
+            .expect("writing to a String cannot fail");
+        print!("{out}");
+        next
+    }
+
+    /// Writes one disassembled instruction and returns the next offset.
+    pub fn write_instruction(
+        &self,
+        out: &mut impl fmt::Write,
+        offset: usize,
+    ) -> Result<usize, fmt::Error> {
+        write!(out, "{offset:04} ")?;
+        let byte = self.code[offset];
+        let line = self.line(offset).unwrap_or(0);
+        if offset > 0 && self.line(offset - 1) == Some(line) {
+            write!(out, "   | ")?;
         } else {
-            print!("{:4} ", line);
+            write!(out, "{:4} ", line)?;
         }
 
         if let Ok(instruction) = OpCode::try_from(byte) {
             match instruction {
-                OpCode::Constant => self.constant_instruction("OP_CONSTANT", offset),
-                OpCode::Add => Self::simple_instruction("OP_ADD", offset),
-                OpCode::Subtract => Self::simple_instruction("OP_SUBTRACT", offset),
-                OpCode::Multiply => Self::simple_instruction("OP_MULTIPLY", offset),
-                OpCode::Divide => Self::simple_instruction("OP_DIVIDE", offset),
-                OpCode::Negate => Self::simple_instruction("OP_NEGATE", offset),
-                OpCode::Return => Self::simple_instruction("OP_RETURN", offset),
+                OpCode::Constant => self.constant_instruction(out, instruction, offset),
+                OpCode::Jump | OpCode::JumpIfFalse => {
+                    self.jump_instruction(out, instruction, offset)
+                }
+                _ => Self::simple_instruction(out, instruction, offset),
             }
         } else {
-            println!("Unknown opcode: {}", byte);
-            offset + 1
+            writeln!(out, "Unknown opcode: {}", byte)?;
+            Ok(offset + 1)
         }
     }
 
-    fn constant_instruction(&self, name: &str, offset: usize) -> usize {
-        let constant = self.code[offset + 1].0;
-        println!(
-            "{:<16} {:4} '{}'",
-            name, constant, self.constants[constant as usize]
-        );
-        offset + 2
+    fn constant_instruction(
+        &self,
+        out: &mut impl fmt::Write,
+        name: OpCode,
+        offset: usize,
+    ) -> Result<usize, fmt::Error> {
+        let Some(&constant) = self.code.get(offset + 1) else {
+            writeln!(out, "{name:<16} <missing operand>")?;
+            return Ok(offset + 1);
+        };
+        match self.constants.get(constant as usize) {
+            Some(value) => writeln!(out, "{:<16} {:4} '{}'", name, constant, value)?,
+            None => writeln!(out, "{:<16} {:4} <invalid constant>", name, constant)?,
+        }
+        Ok(offset + 2)
+    }
+
+    fn jump_instruction(
+        &self,
+        out: &mut impl fmt::Write,
+        name: OpCode,
+        offset: usize,
+    ) -> Result<usize, fmt::Error> {
+        match self.jump_target(offset) {
+            Some(target) => writeln!(out, "{name:<16} {offset:4} -> {target}")?,
+            None => {
+                writeln!(out, "{name:<16} <missing operand>")?;
+                return Ok(self.code.len());
+            }
+        }
+        Ok(offset + 3)
+    }
+
+    fn simple_instruction(
+        out: &mut impl fmt::Write,
+        name: OpCode,
+        offset: usize,
+    ) -> Result<usize, fmt::Error> {
+        writeln!(out, "{name}")?;
+        Ok(offset + 1)
     }
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    let len = u32::try_from(len).expect("chunk sizes fit in u32");
+    out.extend_from_slice(&len.to_le_bytes());
+}
+
+/// A cursor over serialized chunk bytes.
+struct Reader<'a>(&'a [u8]);
 
-    fn simple_instruction(name: &str, offset: usize) -> usize {
-        println!("{name}");
-        offset + 1
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < n {
+            return Err("Truncated chunk.".to_owned());
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        let bytes = self.take(4)?.try_into().expect("took 4 bytes");
+        Ok(u32::from_le_bytes(bytes) as usize)
     }
 }
 
@@ -77,3 +471,189 @@ impl Default for Chunk {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+
+    fn chunk(bytes: &[u8], constants: &[Value]) -> Chunk {
+        let mut chunk = Chunk::new();
+        for &byte in bytes {
+            chunk.write(byte, 1);
+        }
+        chunk.constants.extend_from_slice(constants);
+        chunk
+    }
+
+    #[test]
+    fn line_table() {
+        let mut chunk = Chunk::new();
+        for line in [1, 1, 2, 4, 4, 4] {
+            chunk.write(0, line);
+        }
+        assert_eq!(chunk.lines, vec![(1, 0, 2), (2, 0, 1), (4, 0, 3)]);
+        let lines: Vec<_> = (0..7).map(|offset| chunk.line(offset)).collect();
+        assert_eq!(
+            lines,
+            [Some(1), Some(1), Some(2), Some(4), Some(4), Some(4), None]
+        );
+    }
+
+    #[test]
+    fn positions() {
+        let chunk = Compiler::new("1 +\n  -2;").compile().unwrap();
+        let positions: Vec<_> = (0..chunk.code.len())
+            .map(|offset| chunk.position(offset))
+            .collect();
+        assert_eq!(
+            positions,
+            [
+                Some((1, 1)),
+                Some((1, 1)),
+                Some((2, 4)),
+                Some((2, 4)),
+                Some((2, 4)),
+                Some((2, 4)),
+                Some((2, 5)),
+                Some((2, 6)),
+                Some((2, 6)),
+            ]
+        );
+    }
+
+    #[test]
+    fn bytecode_stats() {
+        let stats = Compiler::new("print 1 + 2 * 3, -4;")
+            .compile()
+            .unwrap()
+            .bytecode_stats();
+        assert_eq!(stats.instructions, 11);
+        assert_eq!(stats.constants, 4);
+        assert_eq!(
+            stats.to_string(),
+            "== script ==\n\
+             instructions: 11\n\
+             bytes:        15\n\
+             constants:    4 (Number: 4)\n\
+             max stack:    3\n\
+             opcodes:\n  \
+             OP_CONSTANT      4\n  \
+             OP_NIL           1\n  \
+             OP_ADD           1\n  \
+             OP_MULTIPLY      1\n  \
+             OP_NEGATE        1\n  \
+             OP_POP           1\n  \
+             OP_PRINT         1\n  \
+             OP_RETURN        1\n"
+        );
+    }
+
+    #[test]
+    fn serialization_round_trips() {
+        let mut chunk = Compiler::new("print 1 +\n-2.5,\n3;").compile().unwrap();
+        let bytes = chunk.to_bytes();
+        let read = Chunk::from_bytes(&bytes).unwrap();
+        assert_eq!(read.name, None);
+        assert_eq!(read.code, chunk.code);
+        assert_eq!(read.lines, chunk.lines);
+        assert_eq!(read.constants, chunk.constants);
+        assert_eq!(read.max_stack, chunk.max_stack);
+
+        for len in 0..bytes.len() {
+            assert!(Chunk::from_bytes(&bytes[..len]).is_err());
+        }
+        assert!(Chunk::from_bytes(b"not a chunk").is_err());
+
+        chunk.name = Some("fib".to_owned());
+        let read = Chunk::from_bytes(&chunk.to_bytes()).unwrap();
+        assert_eq!(read.name.as_deref(), Some("fib"));
+
+        let chunk = Compiler::new("print \"héllo\" + \"\";").compile().unwrap();
+        let read = Chunk::from_bytes(&chunk.to_bytes()).unwrap();
+        assert_eq!(read.constants, [Value::string("héllo"), Value::string("")]);
+    }
+
+    #[test]
+    fn verify_accepts_compiled_code() {
+        let chunk = Compiler::new("print -(1 + 2) * 3, 4;").compile().unwrap();
+        assert_eq!(chunk.verify(256).map(|v| v.stack_size()), Ok(256));
+    }
+
+    #[test]
+    fn verify_follows_jumps() {
+        let compiled = Compiler::new("if (1 and nil) print 2; else print 3 or 4;")
+            .compile()
+            .unwrap();
+        assert_eq!(compiled.verify(256).map(|v| v.stack_size()), Ok(256));
+
+        let constant = OpCode::Constant as u8;
+        let jump = OpCode::Jump as u8;
+        let jump_if_false = OpCode::JumpIfFalse as u8;
+        let pop = OpCode::Pop as u8;
+        let ret = OpCode::Return as u8;
+        let one = [Value::Number(1.0)];
+        // Past the end.
+        assert!(chunk(&[jump, 0, 9, ret], &[]).verify(256).is_err());
+        assert!(chunk(&[jump, 0], &[]).verify(256).is_err());
+        // Only one path pops the condition before they meet.
+        assert_eq!(
+            chunk(
+                &[constant, 0, jump_if_false, 0, 1, pop, constant, 0, ret],
+                &one
+            )
+            .verify(256)
+            .map(|v| v.stack_size()),
+            Err("Inconsistent stack depth at 6.".to_owned())
+        );
+    }
+
+    #[test]
+    fn disassembles_jumps() {
+        let chunk = Compiler::new("print 1 or 2;").compile().unwrap();
+        let listing = chunk.disassembly("or");
+        assert!(listing.contains("OP_JUMP_IF_FALSE    2 -> 8"), "{listing}");
+        assert!(listing.contains("OP_JUMP             5 -> 11"), "{listing}");
+    }
+
+    #[test]
+    fn verify_rejects_malformed_code() {
+        let constant = OpCode::Constant as u8;
+        let ret = OpCode::Return as u8;
+        let add = OpCode::Add as u8;
+        assert!(chunk(&[], &[]).verify(256).is_err());
+        assert!(chunk(&[ret], &[]).verify(256).is_err());
+        assert!(
+            chunk(&[constant], &[Value::Number(1.0)])
+                .verify(256)
+                .is_err()
+        );
+        assert!(
+            chunk(&[constant, 1, ret], &[Value::Number(1.0)])
+                .verify(256)
+                .is_err()
+        );
+        assert!(
+            chunk(&[constant, 0, add, ret], &[Value::Number(1.0)])
+                .verify(256)
+                .is_err()
+        );
+        assert!(
+            chunk(&[constant, 0], &[Value::Number(1.0)])
+                .verify(256)
+                .is_err()
+        );
+        assert!(chunk(&[255], &[]).verify(256).is_err());
+        assert!(
+            chunk(&[constant, 0, constant, 0, ret], &[Value::Number(1.0)])
+                .verify(1)
+                .is_err()
+        );
+        assert_eq!(
+            chunk(&[constant, 0, ret], &[Value::Number(1.0)])
+                .verify(1)
+                .map(|v| v.stack_size()),
+            Ok(1)
+        );
+    }
+}
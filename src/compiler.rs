@@ -1,6 +1,11 @@
+use std::sync::{Arc, Mutex};
+
 use crate::chunk::Chunk;
+use crate::error::Diagnostic;
 use crate::opcode::OpCode;
 use crate::scanner::Scanner;
+use crate::source_map::SourceId;
+use crate::symbol::Interner;
 use crate::token::{Token, TokenKind};
 use crate::value::Value;
 
@@ -27,24 +32,37 @@ struct Parser {
     previous: Token,
     had_error: bool,
     panic_mode: bool,
+    errors: Vec<Diagnostic>,
+    source: Option<SourceId>,
 }
 
 impl Parser {
-    fn new(source: &str) -> Self {
-        let mut scanner = Scanner::new(source);
-        let current = scanner.scan_token();
-        Self {
-            scanner,
-            current,
+    fn new(source: &str, interner: Arc<Mutex<Interner>>, id: Option<SourceId>) -> Self {
+        let mut parser = Self {
+            scanner: Scanner::with_interner(source, interner),
+            current: Token::new(TokenKind::Eof, 0),
             previous: Token::new(TokenKind::Eof, 0),
             had_error: false,
             panic_mode: false,
-        }
+            errors: Vec::new(),
+            source: id,
+        };
+        parser.advance();
+        parser
     }
 
+    /// Moves to the next token, reporting any the scanner could not make
+    /// sense of along the way.
     fn advance(&mut self) {
         std::mem::swap(&mut self.previous, &mut self.current);
-        self.current = self.scanner.scan_token();
+        loop {
+            self.current = self.scanner.scan_token();
+            let TokenKind::Error(message) = &self.current.kind else {
+                break;
+            };
+            let message = message.clone();
+            self.error_at_current(&message);
+        }
     }
 
     fn consume(&mut self, kind: TokenKind, message: &str) {
@@ -55,19 +73,41 @@ impl Parser {
         }
     }
 
+    fn check(&self, kind: &TokenKind) -> bool {
+        self.current.kind == *kind
+    }
+
+    fn match_token(&mut self, kind: TokenKind) -> bool {
+        if !self.check(&kind) {
+            return false;
+        }
+        self.advance();
+        true
+    }
+
+    /// Leaves panic mode by skipping to what looks like the start of the
+    /// next statement, so one mistake does not bury the errors after it.
+    fn synchronize(&mut self) {
+        self.panic_mode = false;
+        while self.current.kind != TokenKind::Eof {
+            if self.previous.kind == TokenKind::Semicolon {
+                return;
+            }
+            if matches!(self.current.kind, TokenKind::If | TokenKind::Print) {
+                return;
+            }
+            self.advance();
+        }
+    }
+
     fn error_at(&mut self, token: Token, message: &str) {
         if self.panic_mode {
             return;
         }
         self.panic_mode = true;
-        eprint!("[line {}] Error", token.line);
-        match token.kind {
-            TokenKind::Error(_) => (),
-            _ => {
-                eprint!(" '{}'", token.lexeme());
-            }
-        }
-        eprintln!(": {message}");
+        let mut diagnostic = Diagnostic::at(&token, message);
+        diagnostic.source = self.source;
+        self.errors.push(diagnostic);
         self.had_error = true;
     }
 
@@ -80,34 +120,110 @@ impl Parser {
     }
 }
 
+/// How deeply expressions may nest before compilation gives up, so that
+/// hostile input cannot overflow the native stack.
+pub const MAX_NESTING: usize = 200;
+
+/// Settings for a [`Compiler`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompileOptions {
+    dump_bytecode: bool,
+    source: Option<SourceId>,
+}
+
+impl CompileOptions {
+    /// Print the disassembled chunk to stdout once it compiles.
+    pub fn with_dump_bytecode(mut self, dump: bool) -> Self {
+        self.dump_bytecode = dump;
+        self
+    }
+
+    /// The file being compiled, recorded on the chunk and on errors.
+    pub fn with_source(mut self, source: SourceId) -> Self {
+        self.source = Some(source);
+        self
+    }
+}
+
 pub struct Compiler {
     parser: Parser,
     chunk: Chunk,
+    depth: usize,
+    stack_depth: usize,
+    options: CompileOptions,
 }
 
 impl Compiler {
     pub fn new(source: &str) -> Self {
+        Self::with_options(source, CompileOptions::default())
+    }
+
+    pub fn with_options(source: &str, options: CompileOptions) -> Self {
+        Self::with_interner(source, options, Arc::default())
+    }
+
+    /// A compiler that names identifiers in `interner`, such as the one of
+    /// the VM that will run the chunk.
+    pub fn with_interner(
+        source: &str,
+        options: CompileOptions,
+        interner: Arc<Mutex<Interner>>,
+    ) -> Self {
+        let parser = Parser::new(source, interner, options.source);
+        let mut chunk = Chunk::new();
+        chunk.source = options.source;
         Self {
-            parser: Parser::new(source),
-            chunk: Chunk::new(),
+            parser,
+            chunk,
+            depth: 0,
+            stack_depth: 0,
+            options,
         }
     }
+
     fn current_chunk(&mut self) -> &mut Chunk {
         &mut self.chunk
     }
 
     fn emit_byte(&mut self, byte: u8) {
-        let line = self.parser.previous.line;
-        self.current_chunk().write(byte, line);
+        let Token { line, column, .. } = self.parser.previous;
+        self.current_chunk().write_at(byte, line, column);
     }
 
-    fn emit_bytes(&mut self, byte1: u8, byte2: u8) {
-        self.emit_byte(byte1);
-        self.emit_byte(byte2);
+    /// Emits an instruction's opcode, tracking its effect on the stack.
+    fn emit_op(&mut self, op: OpCode) {
+        self.stack_depth = self.stack_depth.saturating_add_signed(op.stack_effect());
+        let depth = self.stack_depth;
+        let chunk = self.current_chunk();
+        chunk.max_stack = chunk.max_stack.max(depth);
+        self.emit_byte(op.into());
+    }
+
+    /// Emits a jump with a placeholder offset, returning where the offset
+    /// is for [`Compiler::patch_jump`] to fill in.
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+        self.current_chunk().code.len() - 2
     }
 
+    /// Points the jump whose offset is at `offset` to the next instruction
+    /// to be emitted.
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.current_chunk().code.len() - offset - 2;
+        let Ok(jump) = u16::try_from(jump) else {
+            self.parser.error("Too much code to jump over.");
+            return;
+        };
+        let code = &mut self.current_chunk().code;
+        code[offset..offset + 2].copy_from_slice(&jump.to_be_bytes());
+    }
+
+    /// Returns `nil`, which is what a script evaluates to.
     fn emit_return(&mut self) {
-        self.emit_byte(OpCode::Return.into());
+        self.emit_op(OpCode::Nil);
+        self.emit_op(OpCode::Return);
     }
 
     fn make_constant(&mut self, value: Value) -> u8 {
@@ -121,17 +237,18 @@ impl Compiler {
 
     fn emit_constant(&mut self, value: Value) {
         let byte2 = self.make_constant(value);
-        self.emit_bytes(OpCode::Constant.into(), byte2);
+        self.emit_op(OpCode::Constant);
+        self.emit_byte(byte2);
     }
 
-    fn end_compiler(&mut self) {
-        self.emit_return();
-        #[cfg(debug_assertions)]
-        {
-            if !self.parser.had_error {
-                self.chunk.disassemble("code");
-            }
+    fn end_compiler(&mut self) -> Option<Chunk> {
+        if self.parser.had_error {
+            return None;
+        }
+        if self.options.dump_bytecode {
+            self.chunk.disassemble("code");
         }
+        Some(self.chunk.clone())
     }
 
     fn advance(&mut self) {
@@ -148,7 +265,8 @@ impl Compiler {
         let operator_kind = self.parser.previous.kind.clone();
         self.parse_precedence(Prec::Unary);
         match operator_kind {
-            TokenKind::Minus => self.emit_byte(OpCode::Negate.into()),
+            TokenKind::Bang => self.emit_op(OpCode::Not),
+            TokenKind::Minus => self.emit_op(OpCode::Negate),
             _ => unreachable!(),
         }
     }
@@ -159,30 +277,98 @@ impl Compiler {
         self.parse_precedence(next_prec(&rule_prec));
 
         match &operator_kind {
-            TokenKind::Plus => self.emit_byte(OpCode::Add.into()),
-            TokenKind::Minus => self.emit_byte(OpCode::Subtract.into()),
-            TokenKind::Star => self.emit_byte(OpCode::Multiply.into()),
-            TokenKind::Slash => self.emit_byte(OpCode::Divide.into()),
+            TokenKind::Plus => self.emit_op(OpCode::Add),
+            TokenKind::Minus => self.emit_op(OpCode::Subtract),
+            TokenKind::Star => self.emit_op(OpCode::Multiply),
+            TokenKind::Slash => self.emit_op(OpCode::Divide),
+            TokenKind::EqualEqual => self.emit_op(OpCode::Equal),
+            TokenKind::BangEqual => {
+                self.emit_op(OpCode::Equal);
+                self.emit_op(OpCode::Not);
+            }
+            TokenKind::Greater => self.emit_op(OpCode::Greater),
+            TokenKind::GreaterEqual => {
+                self.emit_op(OpCode::Less);
+                self.emit_op(OpCode::Not);
+            }
+            TokenKind::Less => self.emit_op(OpCode::Less),
+            TokenKind::LessEqual => {
+                self.emit_op(OpCode::Greater);
+                self.emit_op(OpCode::Not);
+            }
             _ => unreachable!(),
         }
     }
 
+    /// `and` short-circuits: if the left operand is falsey, it is the
+    /// result and the right is skipped.
+    fn and(&mut self) {
+        let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.parse_precedence(Prec::And);
+        self.patch_jump(end_jump);
+    }
+
+    /// `or` short-circuits: if the left operand is truthy, it is the result
+    /// and the right is skipped.
+    fn or(&mut self) {
+        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+        let end_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(else_jump);
+        self.emit_op(OpCode::Pop);
+        self.parse_precedence(Prec::Or);
+        self.patch_jump(end_jump);
+    }
+
     fn parse_precedence(&mut self, precedence: Prec) {
+        if self.depth >= MAX_NESTING {
+            self.parser.error_at_current("Expression nesting too deep.");
+            return;
+        }
+        self.depth += 1;
+        self.parse_operand(precedence);
+        self.depth -= 1;
+    }
+
+    fn parse_operand(&mut self, precedence: Prec) {
         match self.parser.current.kind.clone() {
             TokenKind::Number(s) => {
                 self.advance();
-                self.emit_constant(s.parse().expect("failed to parse '{s}'"));
+                match s.parse() {
+                    Ok(n) => self.emit_constant(Value::Number(n)),
+                    Err(_) => self.parser.error("Invalid number."),
+                }
+            }
+            TokenKind::String(s) => {
+                self.advance();
+                // The token keeps its quotes.
+                self.emit_constant(Value::string(&s[1..s.len() - 1]));
+            }
+            TokenKind::Nil => {
+                self.advance();
+                self.emit_op(OpCode::Nil);
+            }
+            TokenKind::True => {
+                self.advance();
+                self.emit_op(OpCode::True);
+            }
+            TokenKind::False => {
+                self.advance();
+                self.emit_op(OpCode::False);
             }
             TokenKind::LeftParen => {
                 self.advance();
                 self.grouping();
             }
-            TokenKind::Minus => {
+            TokenKind::Bang | TokenKind::Minus => {
                 self.advance();
                 self.unary();
             }
             _ => {
-                self.parser.error_at_current("Expect expression.");
+                // Consume the token so that error recovery always makes
+                // progress.
+                self.advance();
+                self.parser.error("Expect expression.");
                 return;
             }
         }
@@ -190,33 +376,129 @@ impl Compiler {
         while precedence <= get_precedence(&self.parser.current.kind) {
             self.advance();
             match self.parser.previous.kind.clone() {
-                TokenKind::Plus | TokenKind::Minus | TokenKind::Star | TokenKind::Slash => {
+                TokenKind::Plus
+                | TokenKind::Minus
+                | TokenKind::Star
+                | TokenKind::Slash
+                | TokenKind::EqualEqual
+                | TokenKind::BangEqual
+                | TokenKind::Greater
+                | TokenKind::GreaterEqual
+                | TokenKind::Less
+                | TokenKind::LessEqual => {
                     self.binary();
                 }
+                TokenKind::And => self.and(),
+                TokenKind::Or => self.or(),
                 _ => return,
             }
         }
     }
 
+    /// Compiles a full expression, including the comma operator: each
+    /// operand is evaluated left to right and all but the last are popped.
+    ///
+    /// Anything that separates items with commas itself (argument lists,
+    /// list literals) must compile each item with `Prec::Assignment` instead,
+    /// so the comma is left for it to consume.
     fn expression(&mut self) {
         self.parse_precedence(Prec::Assignment);
+        while self.parser.current.kind == TokenKind::Comma {
+            self.advance();
+            self.emit_op(OpCode::Pop);
+            self.parse_precedence(Prec::Assignment);
+        }
+    }
+
+    /// Error messages reported while compiling, in source order.
+    pub fn errors(&self) -> &[Diagnostic] {
+        &self.parser.errors
+    }
+
+    fn declaration(&mut self) {
+        self.statement();
+        if self.parser.panic_mode {
+            self.parser.synchronize();
+        }
+    }
+
+    fn statement(&mut self) {
+        if self.parser.match_token(TokenKind::Print) {
+            self.print_statement();
+        } else if self.parser.match_token(TokenKind::If) {
+            self.if_statement();
+        } else {
+            self.expression_statement();
+        }
+    }
+
+    fn print_statement(&mut self) {
+        self.expression();
+        self.parser
+            .consume(TokenKind::Semicolon, "Expect ';' after value.");
+        self.emit_op(OpCode::Print);
     }
 
+    fn if_statement(&mut self) {
+        self.parser
+            .consume(TokenKind::LeftParen, "Expect '(' after 'if'.");
+        self.expression();
+        self.parser
+            .consume(TokenKind::RightParen, "Expect ')' after condition.");
+
+        // The condition stays on the stack until each branch pops it.
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.statement();
+        let else_jump = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(then_jump);
+        // Control arrives here from the jump, with the condition that the
+        // then branch popped still on the stack.
+        self.stack_depth += 1;
+        self.emit_op(OpCode::Pop);
+        if self.parser.match_token(TokenKind::Else) {
+            self.statement();
+        }
+        self.patch_jump(else_jump);
+    }
+
+    fn expression_statement(&mut self) {
+        self.expression();
+        self.parser
+            .consume(TokenKind::Semicolon, "Expect ';' after expression.");
+        self.emit_op(OpCode::Pop);
+    }
+
+    /// Compiles the source as a script: a sequence of statements.
     pub fn compile(&mut self) -> Option<Chunk> {
+        while !self.parser.match_token(TokenKind::Eof) {
+            self.declaration();
+        }
+        self.emit_return();
+        self.end_compiler()
+    }
+
+    /// Compiles the source as a single expression, which the chunk returns
+    /// instead of printing. For hosts that want a value, such as the REPL's
+    /// `:type`.
+    pub fn compile_expression(&mut self) -> Option<Chunk> {
         self.expression();
         self.parser
             .consume(TokenKind::Eof, "Expect end of expression.");
-        self.end_compiler();
-        if self.parser.had_error {
-            None
-        } else {
-            Some(self.chunk.clone())
-        }
+        self.emit_op(OpCode::Return);
+        self.end_compiler()
     }
 }
 
 fn get_precedence(kind: &TokenKind) -> Prec {
     match kind {
+        TokenKind::Or => Prec::Or,
+        TokenKind::And => Prec::And,
+        TokenKind::EqualEqual | TokenKind::BangEqual => Prec::Equality,
+        TokenKind::Greater | TokenKind::GreaterEqual | TokenKind::Less | TokenKind::LessEqual => {
+            Prec::Comparison
+        }
         TokenKind::Plus | TokenKind::Minus => Prec::Term,
         TokenKind::Star | TokenKind::Slash => Prec::Factor,
         _ => Prec::None,
@@ -239,3 +521,174 @@ fn next_prec(prec: &Prec) -> Prec {
         Primary => Primary,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(source: &str) -> Vec<String> {
+        let mut compiler = Compiler::new(source);
+        compiler.compile();
+        compiler.errors().iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth| format!("{}1{};", "(".repeat(depth), ")".repeat(depth));
+        assert!(errors(&nested(MAX_NESTING - 1)).is_empty());
+        assert_eq!(
+            errors(&nested(MAX_NESTING)),
+            vec!["[line 1] Error '1': Expression nesting too deep."]
+        );
+        assert!(!errors(&"-".repeat(100_000)).is_empty());
+    }
+
+    fn max_stack(source: &str) -> usize {
+        Compiler::new(source).compile().unwrap().max_stack
+    }
+
+    #[test]
+    fn stack_accounting() {
+        assert_eq!(max_stack(""), 1);
+        assert_eq!(max_stack("1;"), 1);
+        assert_eq!(max_stack("1 + 2 + 3 + 4;"), 2);
+        assert_eq!(max_stack("print 1 + (2 + (3 + 4));"), 4);
+        assert_eq!(max_stack("-(1 * 2), 3;"), 2);
+    }
+
+    #[test]
+    fn comma_operator() {
+        assert!(errors("1, 2, 3;").is_empty());
+        assert_eq!(errors("1,"), vec!["[line 1] Error '': Expect expression."]);
+        assert_eq!(
+            errors(", 1"),
+            vec!["[line 1] Error ',': Expect expression."]
+        );
+    }
+
+    #[test]
+    fn statements() {
+        let chunk = Compiler::new("print 1;\n2;").compile().unwrap();
+        let ops: Vec<OpCode> = [0, 2, 3, 5, 6, 7]
+            .map(|offset| OpCode::try_from(chunk.code[offset]).unwrap())
+            .to_vec();
+        assert_eq!(
+            ops,
+            [
+                OpCode::Constant,
+                OpCode::Print,
+                OpCode::Constant,
+                OpCode::Pop,
+                OpCode::Nil,
+                OpCode::Return,
+            ]
+        );
+        assert_eq!(
+            errors("print 1"),
+            vec!["[line 1] Error '': Expect ';' after value."]
+        );
+        assert_eq!(
+            errors("1 2;"),
+            vec!["[line 1] Error '2': Expect ';' after expression."]
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        let chunk = Compiler::new("if (true) print 1; else 2;")
+            .compile()
+            .unwrap();
+        assert_eq!(
+            chunk.code[..9],
+            [
+                OpCode::True as u8,
+                OpCode::JumpIfFalse as u8,
+                0,
+                7,
+                OpCode::Pop as u8,
+                OpCode::Constant as u8,
+                0,
+                OpCode::Print as u8,
+                OpCode::Jump as u8,
+            ]
+        );
+        assert_eq!(chunk.jump_target(1), Some(11));
+        assert_eq!(chunk.jump_target(8), Some(15));
+        assert_eq!(chunk.verify(256).map(|v| v.stack_size()), Ok(256));
+
+        assert_eq!(max_stack("print 1 and 2 or 3 + 4;"), 2);
+        assert_eq!(
+            max_stack("if (1 or 2) if (3) print 4; else print 5 + 6;"),
+            2
+        );
+        assert_eq!(
+            errors("if 1) print 2;\nif (1 print 3;"),
+            vec![
+                "[line 1] Error '1': Expect '(' after 'if'.",
+                "[line 2] Error 'print': Expect ')' after condition.",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_scanner_errors() {
+        let mut compiler = Compiler::new("print 1;\n  print \"abc;");
+        assert!(compiler.compile().is_none());
+        let diagnostics = compiler.errors();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "[line 2] Error: Unterminated string."
+        );
+        assert_eq!(diagnostics[0].column, 9);
+        assert_eq!(
+            errors("@ print 1;"),
+            vec!["[line 1] Error: Unexpected character."]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_unicode_escapes() {
+        assert_eq!(
+            errors("print \"a\\u{zz}b\";\nprint \"\\u{110000}\";\nprint \"\\u{41}\";"),
+            vec![
+                "[line 1] Error: Invalid unicode escape '\\u{zz}'.",
+                "[line 2] Error: Invalid unicode escape '\\u{110000}'.",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_an_error_per_statement() {
+        assert_eq!(
+            errors("print;\n1 +;\nprint 2;\n)"),
+            vec![
+                "[line 1] Error ';': Expect expression.",
+                "[line 2] Error ';': Expect expression.",
+                "[line 4] Error ')': Expect expression.",
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_expression() {
+        let chunk = Compiler::new("1 + 2").compile_expression().unwrap();
+        assert_eq!(chunk.code.last(), Some(&OpCode::Return.into()));
+        assert_eq!(chunk.max_stack, 2);
+        let mut compiler = Compiler::new("print 1;");
+        assert!(compiler.compile_expression().is_none());
+        assert_eq!(
+            compiler.errors()[0].to_string(),
+            "[line 1] Error 'print': Expect expression."
+        );
+    }
+
+    #[test]
+    fn malformed_sources_do_not_panic() {
+        for source in [
+            ";", ")", "1 +", "((", "\"", "1 2 3", "-", "* 1", "@", "1..2", "🦀",
+        ] {
+            assert!(!errors(source).is_empty(), "{source:?}");
+        }
+    }
+}
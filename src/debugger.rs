@@ -0,0 +1,280 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+use crate::chunk::Chunk;
+use crate::compiler::Compiler;
+use crate::error::Diagnostic;
+use crate::value::Value;
+use crate::vm::{FrameInfo, Interpret, StepResult, VM};
+
+/// Why execution paused.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    /// About to execute the first instruction of a line with a breakpoint.
+    Breakpoint(usize),
+    /// A `step` or `next` completed.
+    Step,
+    /// The chunk ran to completion (or failed).
+    Finished(Interpret),
+}
+
+/// Drives a VM one instruction at a time over a compiled script.
+pub struct Debugger {
+    vm: VM,
+    chunk: Chunk,
+    source: String,
+    breakpoints: BTreeSet<usize>,
+    finished: Option<Interpret>,
+}
+
+impl Debugger {
+    /// Compiles `source`, returning the compile errors if it fails.
+    pub fn new(source: &str) -> Result<Self, Vec<Diagnostic>> {
+        let mut compiler = Compiler::new(source);
+        let Some(chunk) = compiler.compile() else {
+            return Err(compiler.errors().to_vec());
+        };
+        let mut vm = VM::new();
+        vm.prepare(chunk.clone());
+        Ok(Self {
+            vm,
+            chunk,
+            source: source.to_owned(),
+            breakpoints: BTreeSet::new(),
+            finished: None,
+        })
+    }
+
+    pub fn set_breakpoint(&mut self, line: usize) {
+        self.breakpoints.insert(line);
+    }
+
+    pub fn clear_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.remove(&line)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// The source line of the instruction about to execute, or of the last
+    /// instruction once the program has finished.
+    pub fn line(&self) -> usize {
+        self.vm.frames()[0].line
+    }
+
+    pub fn frames(&self) -> Vec<FrameInfo> {
+        self.vm.frames()
+    }
+
+    pub fn stack(&self) -> &[Value] {
+        self.vm.stack()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.is_some()
+    }
+
+    /// Starts over from the first instruction, stopping there if its line
+    /// has a breakpoint.
+    pub fn run(&mut self) -> Stop {
+        self.vm.prepare(self.chunk.clone());
+        self.finished = None;
+        if self.breakpoints.contains(&self.line()) {
+            return Stop::Breakpoint(self.line());
+        }
+        self.resume()
+    }
+
+    /// Executes exactly one instruction.
+    pub fn step(&mut self) -> Stop {
+        if let Some(result) = &self.finished {
+            return Stop::Finished(result.clone());
+        }
+        let result = match self.vm.step() {
+            StepResult::Continue => return Stop::Step,
+            StepResult::Done(_) => Interpret::Ok,
+            StepResult::Error(error) => Interpret::RuntimeError(error),
+        };
+        self.finished = Some(result.clone());
+        Stop::Finished(result)
+    }
+
+    /// Executes instructions until the current line changes.
+    pub fn next_line(&mut self) -> Stop {
+        let line = self.line();
+        loop {
+            let stop = self.step();
+            if stop != Stop::Step || self.line() != line {
+                return stop;
+            }
+        }
+    }
+
+    /// Runs until a breakpoint line is entered or the chunk finishes.
+    pub fn resume(&mut self) -> Stop {
+        loop {
+            let line = self.line();
+            let stop = self.step();
+            if stop != Stop::Step {
+                return stop;
+            }
+            let next_line = self.line();
+            if next_line != line && self.breakpoints.contains(&next_line) {
+                return Stop::Breakpoint(next_line);
+            }
+        }
+    }
+
+    fn print_location(&self) {
+        self.chunk.disassemble_instruction(self.vm.ip());
+        print!("stack:");
+        for value in self.stack() {
+            print!(" [ {} ]", value);
+        }
+        println!();
+        let line = self.line();
+        if let Some(text) = self.source.lines().nth(line.saturating_sub(1)) {
+            println!("{line:4} | {text}");
+        }
+    }
+
+    fn report(&self, stop: Stop) {
+        match stop {
+            Stop::Breakpoint(line) => {
+                println!("Breakpoint at line {line}.");
+                self.print_location();
+            }
+            Stop::Step => self.print_location(),
+            Stop::Finished(Interpret::RuntimeError(error)) => {
+                println!("{error}");
+                println!("Program finished with a runtime error.");
+            }
+            Stop::Finished(_) => println!("Program finished."),
+        }
+    }
+
+    /// Reads debugger commands from stdin until `quit` or end of input.
+    pub fn repl(&mut self) {
+        let stdin = io::stdin();
+        let mut started = false;
+
+        loop {
+            print!("(rlox) ");
+            io::stdout().flush().expect("failed to flush stdout");
+
+            let mut line = String::new();
+            if stdin
+                .lock()
+                .read_line(&mut line)
+                .expect("failed to read line")
+                == 0
+            {
+                println!();
+                break;
+            }
+
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or("");
+            let argument = words.next().map(str::parse::<usize>);
+
+            match (command, argument) {
+                ("", _) => {}
+                ("break" | "b", Some(Ok(line))) => {
+                    self.set_breakpoint(line);
+                    println!("Breakpoint set at line {line}.");
+                }
+                ("delete" | "d", Some(Ok(line))) => {
+                    if !self.clear_breakpoint(line) {
+                        println!("No breakpoint at line {line}.");
+                    }
+                }
+                ("break" | "b" | "delete" | "d", _) => println!("Expect a line number."),
+                ("info" | "i", _) => {
+                    for line in self.breakpoints() {
+                        println!("Breakpoint at line {line}.");
+                    }
+                }
+                ("run" | "r", _) => {
+                    started = true;
+                    let stop = self.run();
+                    self.report(stop);
+                }
+                (_, _) if !started && command != "quit" && command != "q" => {
+                    println!("The program is not running. Use 'run' to start it.");
+                }
+                ("backtrace" | "bt", _) => {
+                    for frame in self.frames() {
+                        println!("at {} (line {})", frame.function, frame.line);
+                        for (name, value) in &frame.locals {
+                            println!("    {name} = {}", value);
+                        }
+                    }
+                }
+                ("step" | "s", _) => {
+                    let stop = self.step();
+                    self.report(stop);
+                }
+                ("next" | "n", _) => {
+                    let stop = self.next_line();
+                    self.report(stop);
+                }
+                ("continue" | "c", _) => {
+                    let stop = self.resume();
+                    self.report(stop);
+                }
+                ("quit" | "q", _) => break,
+                _ => println!(
+                    "Unknown command '{command}'. Commands: break N, delete N, info, run, step, next, continue, backtrace, quit."
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "print 1 +\n2 *\n3;";
+
+    #[test]
+    fn stops_at_breakpoints() {
+        let mut debugger = Debugger::new(SOURCE).unwrap();
+        debugger.set_breakpoint(2);
+        debugger.set_breakpoint(3);
+        assert_eq!(debugger.run(), Stop::Breakpoint(2));
+        assert_eq!(debugger.stack(), &[Value::Number(1.0)]);
+        assert_eq!(debugger.resume(), Stop::Breakpoint(3));
+        assert_eq!(debugger.stack(), &[Value::Number(1.0), Value::Number(2.0)]);
+        assert_eq!(debugger.resume(), Stop::Finished(Interpret::Ok));
+        assert!(debugger.is_finished());
+    }
+
+    #[test]
+    fn breakpoint_on_first_line() {
+        let mut debugger = Debugger::new(SOURCE).unwrap();
+        debugger.set_breakpoint(1);
+        assert_eq!(debugger.run(), Stop::Breakpoint(1));
+        assert!(debugger.stack().is_empty());
+    }
+
+    #[test]
+    fn step_and_next() {
+        let mut debugger = Debugger::new(SOURCE).unwrap();
+        debugger.set_breakpoint(1);
+        debugger.run();
+        assert_eq!(debugger.step(), Stop::Step);
+        assert_eq!(debugger.stack(), &[Value::Number(1.0)]);
+        assert_eq!(debugger.line(), 2);
+        assert_eq!(debugger.next_line(), Stop::Step);
+        assert_eq!(debugger.line(), 3);
+        assert_eq!(debugger.resume(), Stop::Finished(Interpret::Ok));
+        assert_eq!(debugger.step(), Stop::Finished(Interpret::Ok));
+    }
+
+    #[test]
+    fn compile_errors() {
+        assert!(Debugger::new("1 +").is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! What went wrong, for hosts to report however they like. Nothing below
+//! the CLI prints errors itself; compile and run results carry these.
+
+use std::fmt;
+
+use crate::source_map::SourceId;
+use crate::token::{Token, TokenKind};
+
+/// A compile error at a token in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub line: usize,
+    /// 1-based, or 0 when unknown. See [`Token::column`].
+    pub column: usize,
+    /// The offending token's text, or `None` when the token was itself a
+    /// scanner error.
+    pub lexeme: Option<String>,
+    pub message: String,
+    /// The file it is in, if the compiler was told.
+    pub source: Option<SourceId>,
+}
+
+impl Diagnostic {
+    pub fn at(token: &Token, message: &str) -> Self {
+        let lexeme = match token.kind {
+            TokenKind::Error(_) => None,
+            _ => Some(token.lexeme().to_owned()),
+        };
+        Self {
+            line: token.line,
+            column: token.column,
+            lexeme,
+            message: message.to_owned(),
+            source: None,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Error", self.line)?;
+        if let Some(lexeme) = &self.lexeme {
+            write!(f, " '{lexeme}'")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// One frame of a runtime error's stack trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFrame {
+    /// The function's name, from its chunk, or `None` for top-level code.
+    pub function: Option<String>,
+    /// The file the function is in, if the compiler was told.
+    pub source: Option<SourceId>,
+    pub line: usize,
+}
+
+/// `[line N] in fib()`, or `[line N] in script`, as clox prints it.
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.function {
+            Some(name) => write!(f, "[line {}] in {name}()", self.line),
+            None => write!(f, "[line {}] in script", self.line),
+        }
+    }
+}
+
+/// A failure while running a chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    /// Where it happened.
+    pub line: usize,
+    /// 1-based, or 0 when unknown.
+    pub column: usize,
+    /// The call stack at the time, innermost first.
+    pub trace: Vec<TraceFrame>,
+}
+
+impl RuntimeError {
+    /// An error at `line` with the call stack `trace`, innermost first.
+    pub fn new(message: impl Into<String>, line: usize, trace: Vec<TraceFrame>) -> Self {
+        Self {
+            message: message.into(),
+            line,
+            column: 0,
+            trace,
+        }
+    }
+
+    /// An error in top-level code.
+    pub fn in_script(message: impl Into<String>, line: usize) -> Self {
+        Self::new(
+            message,
+            line,
+            vec![TraceFrame {
+                function: None,
+                source: None,
+                line,
+            }],
+        )
+    }
+
+    pub fn with_column(mut self, column: usize) -> Self {
+        self.column = column;
+        self
+    }
+
+    /// The error as the CLI shows it for the script at `path`: located by
+    /// path, line, and column, with the offending line of `source` and a
+    /// caret under the column, then the trace.
+    pub fn render(&self, path: &str, source: &str) -> String {
+        self.render_with_files(path, source, |_| None)
+    }
+
+    /// Like [`RuntimeError::render`], following each frame with the file
+    /// name `file_of` gives for it, if any.
+    pub(crate) fn render_with_files<'a>(
+        &self,
+        path: &str,
+        source: &str,
+        file_of: impl Fn(&TraceFrame) -> Option<&'a str>,
+    ) -> String {
+        let mut out = format!("{path}:{}", self.line);
+        if self.column > 0 {
+            out.push_str(&format!(":{}", self.column));
+        }
+        out.push_str(&format!(": {}\n", self.message));
+        if let Some(text) = source.lines().nth(self.line.wrapping_sub(1)) {
+            out.push_str(&format!("{:4} | {text}\n", self.line));
+            if self.column > 0 {
+                // Reuse the line's own tabs so the caret lines up however
+                // wide the terminal draws them.
+                let indent: String = text
+                    .chars()
+                    .take(self.column - 1)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                out.push_str(&format!("     | {indent}^\n"));
+            }
+        }
+        for frame in &self.trace {
+            match file_of(frame) {
+                Some(file) => out.push_str(&format!("{frame} ({file})\n")),
+                None => out.push_str(&format!("{frame}\n")),
+            }
+        }
+        out
+    }
+}
+
+/// The message, then one line per frame, as clox prints it.
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for frame in &self.trace {
+            write!(f, "\n{frame}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_like_clox() {
+        let token = Token::new(TokenKind::Plus, 3);
+        assert_eq!(
+            Diagnostic::at(&token, "Expect expression.").to_string(),
+            "[line 3] Error '+': Expect expression."
+        );
+        let token = Token::new(TokenKind::Error("Unexpected character.".to_owned()), 1);
+        assert_eq!(
+            Diagnostic::at(&token, "Unexpected character.").to_string(),
+            "[line 1] Error: Unexpected character."
+        );
+        assert_eq!(
+            RuntimeError::in_script("Stack overflow.", 2).to_string(),
+            "Stack overflow.\n[line 2] in script"
+        );
+        let frame = |function: Option<&str>, line| TraceFrame {
+            function: function.map(str::to_owned),
+            source: None,
+            line,
+        };
+        let error = RuntimeError::new(
+            "Operand must be a number.",
+            4,
+            vec![frame(Some("fib"), 4), frame(None, 9)],
+        );
+        assert_eq!(
+            error.to_string(),
+            "Operand must be a number.\n[line 4] in fib()\n[line 9] in script"
+        );
+    }
+
+    #[test]
+    fn renders_source_line() {
+        let error = RuntimeError::in_script("Stack overflow.", 2).with_column(7);
+        assert_eq!(
+            error.render("main.lox", "1\n\t1 + (2 + 3)\n"),
+            "main.lox:2:7: Stack overflow.\n   2 | \t1 + (2 + 3)\n     | \t     ^\n[line 2] in script\n"
+        );
+        let error = RuntimeError::in_script("Stack underflow.", 9);
+        assert_eq!(
+            error.render("main.lox", "1"),
+            "main.lox:9: Stack underflow.\n[line 9] in script\n"
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! C ABI for embedding the interpreter in non-Rust hosts.
+//!
+//! A host creates a VM with `rlox_vm_new`, runs source through
+//! `rlox_interpret` as often as it likes, and releases it with
+//! `rlox_vm_free`. Results are the same `sysexits.h` codes the CLI uses,
+//! and `rlox_last_error` holds what the CLI would have printed for them.
+
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::ptr;
+
+use crate::vm::VM;
+
+/// Status returned when the arguments themselves are unusable.
+pub const RLOX_INVALID_ARGUMENT: c_int = -1;
+
+/// A VM handed out to C, along with the report of its last failed run.
+pub struct RloxVm {
+    vm: VM,
+    last_error: Option<CString>,
+}
+
+impl RloxVm {
+    fn set_error(&mut self, report: &str) {
+        // C strings end at the first NUL, so escape any the report holds.
+        let report = CString::new(report.replace('\0', "\\0")).expect("NULs were escaped");
+        self.last_error = Some(report);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn rlox_vm_new() -> *mut RloxVm {
+    Box::into_raw(Box::new(RloxVm {
+        vm: VM::new(),
+        last_error: None,
+    }))
+}
+
+/// Compiles and runs a NUL-terminated UTF-8 `source` on `vm`.
+///
+/// # Safety
+///
+/// `vm` must come from `rlox_vm_new` and not have been freed, and `source`
+/// must point to a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rlox_interpret(vm: *mut RloxVm, source: *const c_char) -> c_int {
+    if vm.is_null() || source.is_null() {
+        return RLOX_INVALID_ARGUMENT;
+    }
+    let vm = unsafe { &mut *vm };
+    vm.last_error = None;
+    let Ok(source) = unsafe { CStr::from_ptr(source) }.to_str() else {
+        vm.set_error("Source is not valid UTF-8.\n");
+        return RLOX_INVALID_ARGUMENT;
+    };
+    let result = vm.vm.interpret(source);
+    let report = result.error_report();
+    if !report.is_empty() {
+        vm.set_error(&report);
+    }
+    result.exit_code().into()
+}
+
+/// The errors from the last `rlox_interpret` call on `vm`, one per line, or
+/// null if it succeeded. The string belongs to `vm` and stays valid until
+/// the next `rlox_interpret` or `rlox_vm_free` on it.
+///
+/// # Safety
+///
+/// `vm` must be null or come from `rlox_vm_new` and not have been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rlox_last_error(vm: *const RloxVm) -> *const c_char {
+    if vm.is_null() {
+        return ptr::null();
+    }
+    unsafe { &*vm }
+        .last_error
+        .as_deref()
+        .map_or(ptr::null(), CStr::as_ptr)
+}
+
+/// Releases a VM created by `rlox_vm_new`. Passing null is a no-op.
+///
+/// # Safety
+///
+/// `vm` must come from `rlox_vm_new` and must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rlox_vm_free(vm: *mut RloxVm) {
+    if !vm.is_null() {
+        drop(unsafe { Box::from_raw(vm) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error(vm: *const RloxVm) -> Option<String> {
+        let error = unsafe { rlox_last_error(vm) };
+        (!error.is_null()).then(|| {
+            unsafe { CStr::from_ptr(error) }
+                .to_str()
+                .unwrap()
+                .to_owned()
+        })
+    }
+
+    #[test]
+    fn lifecycle() {
+        let vm = rlox_vm_new();
+        unsafe {
+            assert_eq!(rlox_interpret(vm, c"print 1 + 2;".as_ptr()), 0);
+            assert_eq!(rlox_interpret(vm, c"1 +".as_ptr()), 65);
+            assert_eq!(
+                rlox_interpret(std::ptr::null_mut(), c"1".as_ptr()),
+                RLOX_INVALID_ARGUMENT
+            );
+            rlox_vm_free(vm);
+        }
+    }
+
+    #[test]
+    fn last_error_reports() {
+        let vm = rlox_vm_new();
+        unsafe {
+            assert_eq!(rlox_interpret(vm, c"1 +".as_ptr()), 65);
+            assert_eq!(
+                last_error(vm).as_deref(),
+                Some("[line 1] Error '': Expect expression.\n")
+            );
+            assert_eq!(rlox_interpret(vm, c"-\"a\";".as_ptr()), 70);
+            assert_eq!(
+                last_error(vm).as_deref(),
+                Some("Operand must be a number.\n[line 1] in script\n")
+            );
+            assert_eq!(rlox_interpret(vm, c"print 1;".as_ptr()), 0);
+            assert_eq!(last_error(vm), None);
+            assert!(rlox_last_error(std::ptr::null()).is_null());
+            rlox_vm_free(vm);
+        }
+    }
+}
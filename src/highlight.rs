@@ -0,0 +1,142 @@
+//! Syntax classes for every part of a source, for editors and the web
+//! playground to color.
+
+use crate::scanner;
+use crate::token::{TokenKind, Trivia, TriviaKind};
+
+/// A byte range of the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenClass {
+    Keyword,
+    Identifier,
+    Number,
+    String,
+    Operator,
+    /// Brackets, commas, dots, and semicolons.
+    Punctuation,
+    Comment,
+    Whitespace,
+    /// Text the scanner rejected, such as an unterminated string.
+    Error,
+}
+
+/// Splits `source` into consecutive spans covering all of it, each with
+/// the class to show it as.
+pub fn highlight(source: &str) -> Vec<(Span, TokenClass)> {
+    let mut regions = Vec::new();
+    let mut offset = 0;
+    let mut push = |text: &str, class| {
+        if !text.is_empty() {
+            let span = Span {
+                start: offset,
+                end: offset + text.len(),
+            };
+            regions.push((span, class));
+            offset = span.end;
+        }
+    };
+    for token in scanner::scan_with_trivia(source) {
+        for trivia in &token.leading {
+            push(&trivia.text, trivia_class(trivia));
+        }
+        push(&token.text, token_class(&token.token.kind));
+        for trivia in &token.trailing {
+            push(&trivia.text, trivia_class(trivia));
+        }
+    }
+    regions
+}
+
+fn trivia_class(trivia: &Trivia) -> TokenClass {
+    match trivia.kind {
+        TriviaKind::Comment => TokenClass::Comment,
+        TriviaKind::Whitespace | TriviaKind::Newline | TriviaKind::ByteOrderMark => {
+            TokenClass::Whitespace
+        }
+    }
+}
+
+fn token_class(kind: &TokenKind) -> TokenClass {
+    match kind {
+        TokenKind::LeftParen
+        | TokenKind::RightParen
+        | TokenKind::LeftBrace
+        | TokenKind::RightBrace
+        | TokenKind::Comma
+        | TokenKind::Dot
+        | TokenKind::Semicolon => TokenClass::Punctuation,
+        TokenKind::Minus
+        | TokenKind::Plus
+        | TokenKind::Slash
+        | TokenKind::Star
+        | TokenKind::Bang
+        | TokenKind::BangEqual
+        | TokenKind::Equal
+        | TokenKind::EqualEqual
+        | TokenKind::Greater
+        | TokenKind::GreaterEqual
+        | TokenKind::Less
+        | TokenKind::LessEqual => TokenClass::Operator,
+        TokenKind::Identifier(_) => TokenClass::Identifier,
+        TokenKind::String(_) => TokenClass::String,
+        TokenKind::Number(_) => TokenClass::Number,
+        TokenKind::And
+        | TokenKind::Class
+        | TokenKind::Else
+        | TokenKind::False
+        | TokenKind::For
+        | TokenKind::Fun
+        | TokenKind::If
+        | TokenKind::Nil
+        | TokenKind::Or
+        | TokenKind::Print
+        | TokenKind::Return
+        | TokenKind::Super
+        | TokenKind::This
+        | TokenKind::True
+        | TokenKind::Var
+        | TokenKind::While => TokenClass::Keyword,
+        TokenKind::Error(_) => TokenClass::Error,
+        // Has no text, so never becomes a region.
+        TokenKind::Eof => TokenClass::Whitespace,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_every_region() {
+        let source = "print x + 1.5; // sum\n\"hi\" @";
+        let regions: Vec<(&str, TokenClass)> = highlight(source)
+            .into_iter()
+            .map(|(span, class)| (&source[span.start..span.end], class))
+            .collect();
+        assert_eq!(
+            regions,
+            [
+                ("print", TokenClass::Keyword),
+                (" ", TokenClass::Whitespace),
+                ("x", TokenClass::Identifier),
+                (" ", TokenClass::Whitespace),
+                ("+", TokenClass::Operator),
+                (" ", TokenClass::Whitespace),
+                ("1.5", TokenClass::Number),
+                (";", TokenClass::Punctuation),
+                (" ", TokenClass::Whitespace),
+                ("// sum", TokenClass::Comment),
+                ("\n", TokenClass::Whitespace),
+                ("\"hi\"", TokenClass::String),
+                (" ", TokenClass::Whitespace),
+                ("@", TokenClass::Error),
+            ]
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! REPL history that persists between sessions.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+pub struct History {
+    path: PathBuf,
+    entries: Vec<String>,
+    /// How many of `entries` were loaded from the file.
+    loaded: usize,
+}
+
+impl History {
+    /// `$XDG_STATE_HOME/rlox/history`, falling back to `~/.rlox_history`.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(state) = env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+            return Some(PathBuf::from(state).join("rlox").join("history"));
+        }
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".rlox_history"))
+    }
+
+    /// Loads the history at `path`. A missing file is an empty history.
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let entries: Vec<String> = match fs::read_to_string(&path) {
+            Ok(contents) => contents.lines().map(str::to_owned).collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path,
+            loaded: entries.len(),
+            entries,
+        })
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Records a line the user entered, skipping blank lines and repeats of
+    /// the previous entry.
+    pub fn add(&mut self, line: &str) {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.trim().is_empty() || self.entries.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.entries.push(line.to_owned());
+    }
+
+    /// Appends this session's entries to the file.
+    pub fn save(&mut self) -> io::Result<()> {
+        if self.entries.len() == self.loaded {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        for entry in &self.entries[self.loaded..] {
+            writeln!(file, "{entry}")?;
+        }
+        self.loaded = self.entries.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dir = env::temp_dir().join(format!("rlox-history-{}", std::process::id()));
+        let path = dir.join("history");
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut history = History::load(&path).unwrap();
+        assert!(history.entries().is_empty());
+        history.add("1 + 2\n");
+        history.add("1 + 2\n");
+        history.add("   \n");
+        history.add("3 * 4\n");
+        history.save().unwrap();
+
+        let mut history = History::load(&path).unwrap();
+        assert_eq!(history.entries(), ["1 + 2", "3 * 4"]);
+        history.add("3 * 4");
+        history.add("5");
+        history.save().unwrap();
+        history.save().unwrap();
+
+        let history = History::load(&path).unwrap();
+        assert_eq!(history.entries(), ["1 + 2", "3 * 4", "5"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
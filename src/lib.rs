@@ -1,7 +1,146 @@
+pub mod ast;
+mod backend;
+pub mod c;
+pub mod cache;
+pub mod capture;
+pub mod cfg;
 pub mod chunk;
 pub mod compiler;
+pub mod debugger;
+pub mod error;
+pub mod ffi;
+pub mod highlight;
+pub mod history;
+pub mod metrics;
 pub mod opcode;
+pub mod sandbox;
 pub mod scanner;
+pub mod source_map;
+pub mod symbol;
+pub mod test_runner;
 pub mod token;
+pub mod treewalk;
 pub mod value;
 pub mod vm;
+pub mod wasm;
+
+use std::fmt;
+
+use capture::SharedBuffer;
+use error::{Diagnostic, RuntimeError};
+use value::Value;
+use vm::{Interpret, Stats};
+
+/// Settings for [`run`]. Its output writer is replaced by the capture, and
+/// execution tracing and bytecode dumps, which would bypass it, are off.
+pub use vm::VmOptions as Options;
+
+/// What a successful [`run`] produced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Output {
+    /// The value the script returned, which is `nil` for now, as there are
+    /// no `return` statements yet.
+    pub value: Option<Value>,
+    /// Everything the script printed.
+    pub stdout: String,
+    pub stats: Stats,
+}
+
+/// Why a [`run`] failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Compile(Vec<Diagnostic>),
+    Runtime(RuntimeError),
+    /// The [`Options::with_interrupt`] flag was raised.
+    Interrupted,
+}
+
+impl Error {
+    /// The conventional `sysexits.h` status for this error.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Compile(_) => 65,
+            Error::Runtime(_) => 70,
+            Error::Interrupted => 130,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Compile(errors) => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{error}")?;
+                }
+                Ok(())
+            }
+            Error::Runtime(error) => write!(f, "{error}"),
+            Error::Interrupted => write!(f, "Interrupted."),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Compiles and runs `source` on a fresh VM, capturing what it prints.
+pub fn run(source: &str, options: Options) -> Result<Output, Error> {
+    let stdout = SharedBuffer::new();
+    let mut vm = options
+        .with_trace_execution(false)
+        .with_dump_bytecode(false)
+        .with_output(Box::new(stdout.clone()))
+        .build();
+    match vm.interpret(source) {
+        Interpret::Ok => Ok(Output {
+            value: vm.result(),
+            stdout: stdout.contents(),
+            stats: vm.stats(),
+        }),
+        Interpret::CompileError(errors) => Err(Error::Compile(errors)),
+        Interpret::RuntimeError(error) => Err(Error::Runtime(error)),
+        Interpret::Interrupted => Err(Error::Interrupted),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn captures_output() {
+        let output = run("print 1 + 2;", Options::default()).unwrap();
+        assert_eq!(output.value, Some(Value::Nil));
+        assert_eq!(output.stdout, "3\n");
+        assert_eq!(output.stats.instructions, 6);
+        let options = Options::default()
+            .with_trace_execution(true)
+            .with_dump_bytecode(true);
+        assert_eq!(run("print 1 + 2;", options).unwrap(), output);
+    }
+
+    #[test]
+    fn reports_compile_errors() {
+        let error = run("1 +", Options::default()).unwrap_err();
+        assert_eq!(error.to_string(), "[line 1] Error '': Expect expression.");
+        assert_eq!(error.exit_code(), 65);
+    }
+
+    #[test]
+    fn reports_runtime_errors() {
+        let error = run(
+            "print 1 + 2 + 3;",
+            Options::default().with_instruction_budget(2),
+        )
+        .unwrap_err();
+        assert!(matches!(error, Error::Runtime(_)));
+        assert_eq!(
+            error.to_string(),
+            "Instruction budget exceeded.\n[line 1] in script"
+        );
+        assert_eq!(error.exit_code(), 70);
+    }
+}
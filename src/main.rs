@@ -1,16 +1,68 @@
-use rlox::vm::{Interpret, VM};
+use rlox::ast;
+use rlox::c;
+use rlox::cache::ChunkCache;
+use rlox::cfg::Cfg;
+use rlox::chunk::Chunk;
+use rlox::compiler::Compiler;
+use rlox::debugger::Debugger;
+use rlox::history::History;
+use rlox::scanner;
+use rlox::source_map::{SourceId, SourceMap};
+use rlox::test_runner;
+use rlox::treewalk::Interpreter;
+use rlox::vm::{Interpret, VM, VmOptions};
+use rlox::wasm;
+use std::ffi::c_int;
 use std::io::{self, Write};
+use std::path::Path;
 use std::process::{self, ExitCode};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 use std::{env, fs};
 
 fn main() -> ExitCode {
     let args: Vec<String> = env::args().collect();
 
+    if let Some(options) = ReplOptions::parse(&args[1..]) {
+        repl(options);
+        return ExitCode::SUCCESS;
+    }
+
+    if let Some((options, path)) = RunOptions::parse(&args[1..]) {
+        run_file(path, options);
+        return ExitCode::SUCCESS;
+    }
+
     match args.len() {
-        1 => repl(),
-        2 => run_file(&args[1]),
+        3 if args[1] == "--dump-cfg" => dump_cfg(&args[2]),
+        3 if args[1] == "--stats-bytecode" => print_bytecode_stats(&args[2]),
+        3 if args[1] == "--bench" => bench_file(&args[2], DEFAULT_BENCH_ITERATIONS),
+        5 if args[1] == "--bench" && args[3] == "--iterations" => match args[4].parse() {
+            Ok(iterations) if iterations > 0 => bench_file(&args[2], iterations),
+            _ => {
+                eprintln!("Iterations must be a positive integer.");
+                return ExitCode::from(64);
+            }
+        },
+        3 if args[1] == "debug" => debug_file(&args[2]),
+        3 if args[1] == "parse" => parse_file(&args[2]),
+        3 if args[1] == "--treewalk" => treewalk_file(&args[2]),
+        3 if args[1] == "test" => test_dir(&args[2]),
+        5 if args[1] == "build" && args[2] == "--target" => build_file(&args[3], &args[4]),
         _ => {
-            eprintln!("Usage: rlox [path]");
+            eprintln!("Usage: rlox [--cache] [--stats] [--dump-bytecode] [--trace] <path>");
+            eprintln!(
+                "       rlox [--quiet] [--no-history] [--prompt <text>] [--continuation-prompt <text>]"
+            );
+            eprintln!("       rlox --dump-cfg <path>");
+            eprintln!("       rlox --stats-bytecode <path>");
+            eprintln!("       rlox --bench <path> [--iterations <n>]");
+            eprintln!("       rlox debug <path>");
+            eprintln!("       rlox parse <path>");
+            eprintln!("       rlox --treewalk <path>");
+            eprintln!("       rlox test <path>");
+            eprintln!("       rlox build --target <wasm|c> <path>");
             return ExitCode::from(64);
         }
     }
@@ -18,11 +70,98 @@ fn main() -> ExitCode {
     ExitCode::SUCCESS
 }
 
-fn repl() {
+struct ReplOptions {
+    history: bool,
+    banner: bool,
+    prompt: String,
+    continuation_prompt: String,
+}
+
+impl ReplOptions {
+    /// Parses the arguments after the program name, or returns `None` if
+    /// they are not a REPL invocation.
+    fn parse(args: &[String]) -> Option<Self> {
+        let mut options = Self {
+            history: true,
+            banner: true,
+            prompt: "> ".to_owned(),
+            continuation_prompt: ". ".to_owned(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--no-history" => options.history = false,
+                "--quiet" => options.banner = false,
+                "--prompt" => options.prompt = args.next()?.clone(),
+                "--continuation-prompt" => options.continuation_prompt = args.next()?.clone(),
+                _ => return None,
+            }
+        }
+        Some(options)
+    }
+}
+
+/// How to run a script, from the flags before its path.
+struct RunOptions {
+    vm: VmOptions,
+    /// Reuse the compiled chunk from an earlier run of the same source.
+    cache: bool,
+    /// Report the resources the script used on stderr.
+    stats: bool,
+}
+
+impl RunOptions {
+    /// Parses the arguments after the program name as flags followed by a
+    /// script path, or returns `None` if they are not a script run.
+    fn parse(args: &[String]) -> Option<(Self, &str)> {
+        let (path, flags) = args.split_last()?;
+        if path.starts_with("--") {
+            return None;
+        }
+        let mut options = Self {
+            vm: VM::builder(),
+            cache: false,
+            stats: false,
+        };
+        for flag in flags {
+            match flag.as_str() {
+                "--cache" => options.cache = true,
+                "--stats" => options.stats = true,
+                "--dump-bytecode" => options.vm = options.vm.with_dump_bytecode(true),
+                "--trace" => options.vm = options.vm.with_trace_execution(true),
+                _ => return None,
+            }
+        }
+        Some((options, path))
+    }
+}
+
+fn repl(options: ReplOptions) {
     let stdin = io::stdin();
+    // History is a convenience; a file that cannot be read or written
+    // should not stop the REPL.
+    let mut history = options
+        .history
+        .then(History::default_path)
+        .flatten()
+        .and_then(|path| History::load(path).ok());
+
+    let interrupt = interrupt_on_ctrl_c();
+    let mut sources = SourceMap::new();
+
+    if options.banner {
+        println!("rlox {}", env!("CARGO_PKG_VERSION"));
+        println!("Press Ctrl+D to exit.");
+    }
 
+    let mut source = String::new();
     loop {
-        print!("> ");
+        let prompt = if source.is_empty() {
+            &options.prompt
+        } else {
+            &options.continuation_prompt
+        };
+        print!("{prompt}");
         io::stdout().flush().expect("failed to flush stdout");
 
         let mut line = String::new();
@@ -31,32 +170,358 @@ fn repl() {
         // EOF (Ctrl+D)
         if bytes_read == 0 {
             println!();
+            if !source.is_empty() {
+                // Report whatever was left unfinished.
+                let id = add_repl_source(&mut sources, &source);
+                let result = VM::builder().with_source(id).build().interpret(&source);
+                eprint!("{}", report_in(&result, &sources));
+            }
             break;
         }
 
-        if line.trim().is_empty() {
+        if source.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        if let Some(history) = &mut history {
+            history.add(&line);
+        }
+        if source.is_empty()
+            && let Some(expr) = line.trim().strip_prefix(":type")
+            && (expr.is_empty() || expr.starts_with(char::is_whitespace))
+        {
+            show_type(expr.trim(), &interrupt, &mut sources);
             continue;
         }
 
-        let mut vm = VM::new();
-        let _ = vm.interpret(&line);
+        source.push_str(&line);
+        if scanner::is_incomplete(&source) {
+            continue;
+        }
+
+        // Ctrl+C pressed at the prompt is not meant for this line.
+        interrupt.store(false, Ordering::Relaxed);
+        let id = add_repl_source(&mut sources, &source);
+        let mut vm = VM::builder()
+            .with_interrupt(Arc::clone(&interrupt))
+            .with_source(id)
+            .build();
+        eprint!("{}", report_in(&vm.interpret(&source), &sources));
+        source.clear();
+    }
+
+    if let Some(history) = &mut history
+        && let Err(e) = history.save()
+    {
+        eprintln!("Failed to save history: {e}");
+    }
+}
+
+/// The longest value preview `:type` prints before eliding the rest.
+const TYPE_PREVIEW_LEN: usize = 40;
+
+/// Handles `:type <expr>`: evaluates the expression with its output
+/// discarded and prints the result's type and a short preview.
+fn show_type(expr: &str, interrupt: &Arc<AtomicBool>, sources: &mut SourceMap) {
+    if expr.is_empty() {
+        eprintln!("Usage: :type <expression>");
+        return;
+    }
+    interrupt.store(false, Ordering::Relaxed);
+    let id = add_repl_source(sources, expr);
+    let mut vm = VM::builder()
+        .with_interrupt(Arc::clone(interrupt))
+        .with_source(id)
+        .with_output(Box::new(io::sink()))
+        .build();
+    let result = vm.evaluate(expr);
+    match vm.result() {
+        Some(value) => {
+            let mut preview = value.to_string();
+            if preview.chars().count() > TYPE_PREVIEW_LEN {
+                preview = preview.chars().take(TYPE_PREVIEW_LEN).collect();
+                preview.push_str("...");
+            }
+            println!("{}: {preview}", value.type_name());
+        }
+        None => eprint!("{}", report_in(&result, sources)),
+    }
+}
+
+/// Registers an entry typed at the REPL as a virtual file, `<repl:N>` for
+/// the Nth entry.
+fn add_repl_source(sources: &mut SourceMap, text: &str) -> SourceId {
+    let name = format!("<repl:{}>", sources.len() + 1);
+    sources.add(name, text)
+}
+
+/// Like `report`, for code compiled against a file in `sources`.
+fn report_in(result: &Interpret, sources: &SourceMap) -> String {
+    match result {
+        Interpret::CompileError(errors) => errors
+            .iter()
+            .map(|error| format!("{}\n", sources.render_diagnostic(error)))
+            .collect(),
+        Interpret::RuntimeError(error) => sources.render_runtime_error(error),
+        _ => result.error_report(),
+    }
+}
+
+/// Set by the SIGINT handler and watched by running VMs.
+static INTERRUPT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Makes Ctrl+C stop the running script instead of killing the process,
+/// returning the flag to hand to VMs.
+fn interrupt_on_ctrl_c() -> Arc<AtomicBool> {
+    const SIGINT: c_int = 2;
+
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
     }
+
+    extern "C" fn on_sigint(_: c_int) {
+        if let Some(flag) = INTERRUPT.get() {
+            flag.store(true, Ordering::Relaxed);
+        }
+    }
+
+    let flag = Arc::clone(INTERRUPT.get_or_init(Default::default));
+    // SAFETY: the handler only performs an atomic store, which is
+    // async-signal-safe, on a flag initialized before it is installed.
+    unsafe { signal(SIGINT, on_sigint) };
+    flag
 }
 
-fn run_file(path: &str) {
-    let source = match fs::read_to_string(path) {
+/// A VM for running a whole file, which Ctrl+C interrupts.
+fn file_vm() -> VmOptions {
+    VM::builder().with_interrupt(interrupt_on_ctrl_c())
+}
+
+fn read_file(path: &str) -> String {
+    match fs::read_to_string(path) {
         Ok(s) => s,
         Err(e) => {
             eprintln!("Failed to read file {path}: {e}");
             process::exit(74);
         }
-    };
+    }
+}
 
-    let mut vm = VM::new();
-    let result = vm.interpret(&source);
+/// What to print for `result`, showing a runtime error against the
+/// `source` of the script at `path`.
+fn report(result: &Interpret, path: &str, source: &str) -> String {
     match result {
-        Interpret::CompileError => process::exit(65),
-        Interpret::RuntimeError => process::exit(70),
-        Interpret::Ok => {}
+        Interpret::RuntimeError(error) => error.render(path, source),
+        _ => result.error_report(),
+    }
+}
+
+/// Reports `result`'s errors and exits with its status if it failed.
+fn exit_on_error(result: Interpret, path: &str, source: &str) {
+    if result != Interpret::Ok {
+        eprint!("{}", report(&result, path, source));
+        process::exit(result.exit_code().into());
+    }
+}
+
+/// Runs the script at `path`, which Ctrl+C interrupts.
+fn run_file(path: &str, options: RunOptions) {
+    let source = read_file(path);
+
+    let mut vm = options.vm.with_interrupt(interrupt_on_ctrl_c()).build();
+    let result = if options.cache {
+        vm.run(&cached_chunk(&vm, &source))
+    } else {
+        vm.interpret(&source)
+    };
+    if options.stats {
+        let stats = vm.stats();
+        eprintln!("instructions executed: {}", stats.instructions);
+        eprintln!("peak stack depth:      {}", stats.peak_stack);
+    }
+    exit_on_error(result, path, &source);
+}
+
+/// The compiled chunk from an earlier run of `source` if one is cached, or
+/// else `vm`'s compilation of it, which is cached for next time. A cached
+/// chunk is not compiled again, so it is not dumped either.
+fn cached_chunk(vm: &VM, source: &str) -> Chunk {
+    let cache = ChunkCache::default_dir().map(ChunkCache::new);
+    if let Some(chunk) = cache.as_ref().and_then(|cache| cache.load(source)) {
+        return chunk;
+    }
+    let chunk = vm.compile(source).unwrap_or_else(|errors| {
+        eprint!("{}", Interpret::CompileError(errors).error_report());
+        process::exit(65);
+    });
+    if let Some(cache) = &cache {
+        // A cache that cannot be written only costs speed.
+        let _ = cache.store(source, &chunk);
+    }
+    chunk
+}
+
+/// Prints the control-flow graph of the compiled script as Graphviz DOT,
+/// without running it.
+fn dump_cfg(path: &str) {
+    let source = read_file(path);
+
+    let chunk = compile_or_exit(&source);
+    match Cfg::build(&chunk) {
+        Ok(cfg) => print!("{}", cfg.to_dot(&chunk)),
+        Err(message) => {
+            eprintln!("{message}");
+            process::exit(70);
+        }
+    }
+}
+
+/// Prints the size and makeup of the compiled script's bytecode, without
+/// running it.
+fn print_bytecode_stats(path: &str) {
+    let source = read_file(path);
+
+    print!("{}", compile_or_exit(&source).bytecode_stats());
+}
+
+const DEFAULT_BENCH_ITERATIONS: usize = 10;
+
+/// Compiles the script once, runs it `iterations` times with its output
+/// discarded, and reports how long the runs took.
+fn bench_file(path: &str, iterations: usize) {
+    let source = read_file(path);
+
+    let chunk = compile_or_exit(&source);
+    let mut vm = file_vm().with_output(Box::new(io::sink())).build();
+    let verified = chunk.verify(chunk.max_stack).ok();
+    let mut times = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let result = match &verified {
+            Some(verified) => vm.run_verified(verified),
+            None => vm.run(&chunk),
+        };
+        times.push(start.elapsed());
+        exit_on_error(result, path, &source);
+    }
+
+    times.sort();
+    let middle = times.len() / 2;
+    let median = if times.len() % 2 == 0 {
+        (times[middle - 1] + times[middle]) / 2
+    } else {
+        times[middle]
+    };
+    let mean = times.iter().sum::<Duration>() / times.len() as u32;
+    println!("iterations:   {iterations}");
+    println!("min:          {:?}", times[0]);
+    println!("median:       {median:?}");
+    println!("mean:         {mean:?}");
+    println!("instructions: {} per run", vm.stats().instructions);
+}
+
+/// Compiles `source`, or reports its errors and exits.
+fn compile_or_exit(source: &str) -> Chunk {
+    let mut compiler = Compiler::new(source);
+    let Some(chunk) = compiler.compile() else {
+        for error in compiler.errors() {
+            eprintln!("{error}");
+        }
+        process::exit(65);
+    };
+    chunk
+}
+
+fn debug_file(path: &str) {
+    let source = read_file(path);
+
+    match Debugger::new(&source) {
+        Ok(mut debugger) => debugger.repl(),
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{error}");
+            }
+            process::exit(65);
+        }
+    }
+}
+
+fn parse_file(path: &str) {
+    let source = read_file(path);
+
+    match ast::parse(&source) {
+        Ok(statements) => {
+            for statement in statements {
+                println!("{statement}");
+            }
+        }
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{error}");
+            }
+            process::exit(65);
+        }
+    }
+}
+
+fn treewalk_file(path: &str) {
+    let source = read_file(path);
+
+    exit_on_error(Interpreter::new().interpret(&source), path, &source);
+}
+
+fn test_dir(path: &str) {
+    let summary = match test_runner::run_path(Path::new(path)) {
+        Ok(summary) => summary,
+        Err(e) => {
+            eprintln!("Failed to run tests in {path}: {e}");
+            process::exit(74);
+        }
+    };
+
+    for (path, failures) in &summary.failed {
+        println!("FAIL {}", path.display());
+        for failure in failures {
+            println!("    {failure}");
+        }
+    }
+    println!(
+        "{} passed, {} failed.",
+        summary.passed,
+        summary.failed.len()
+    );
+    if !summary.is_success() {
+        process::exit(1);
+    }
+}
+
+/// Translates a compiled chunk into a build target's output file.
+type Backend = fn(&Chunk) -> Result<Vec<u8>, String>;
+
+/// Compiles the script at `path` for `target`, writing the result next to
+/// it with the target's extension.
+fn build_file(target: &str, path: &str) {
+    let (extension, backend): (_, Backend) = match target {
+        "wasm" => ("wasm", wasm::compile),
+        "c" => ("c", |chunk| c::compile(chunk).map(String::into_bytes)),
+        _ => {
+            eprintln!("Unknown target '{target}'. Targets: wasm, c.");
+            process::exit(64);
+        }
+    };
+    let source = read_file(path);
+
+    let chunk = compile_or_exit(&source);
+    let output = match backend(&chunk) {
+        Ok(output) => output,
+        Err(message) => {
+            eprintln!("{message}");
+            process::exit(70);
+        }
+    };
+
+    let out_path = Path::new(path).with_extension(extension);
+    if let Err(e) = fs::write(&out_path, output) {
+        eprintln!("Failed to write {}: {e}", out_path.display());
+        process::exit(74);
     }
 }
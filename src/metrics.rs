@@ -0,0 +1,49 @@
+//! Counters shared between VMs and the host that runs them.
+//!
+//! A host creates one [`Metrics`] per tenant (or globally), hands it to
+//! every VM it builds with [`VmOptions::with_metrics`], and samples it from
+//! any thread. VMs add to the counters when a run or step finishes rather
+//! than per instruction, so the hot loop stays free of atomics.
+//!
+//! [`VmOptions::with_metrics`]: crate::vm::VmOptions::with_metrics
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    instructions: AtomicU64,
+    runs: AtomicU64,
+    runtime_errors: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Instructions executed across all runs.
+    pub fn instructions(&self) -> u64 {
+        self.instructions.load(Ordering::Relaxed)
+    }
+
+    /// Runs that have finished, successfully or not.
+    pub fn runs(&self) -> u64 {
+        self.runs.load(Ordering::Relaxed)
+    }
+
+    /// Runs that ended in a runtime error.
+    pub fn runtime_errors(&self) -> u64 {
+        self.runtime_errors.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_instructions(&self, count: u64) {
+        self.instructions.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn finish_run(&self, failed: bool) {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.runtime_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -1,14 +1,133 @@
+use std::fmt;
+use std::str::FromStr;
+
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OpCode {
     Constant,
+    Nil,
+    True,
+    False,
     Add,
     Subtract,
     Multiply,
     Divide,
+    Equal,
+    Greater,
+    Less,
     Negate,
+    Not,
+    Pop,
+    Print,
+    Jump,
+    JumpIfFalse,
     Return,
 }
 
+impl OpCode {
+    pub const ALL: [OpCode; 18] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Equal,
+        OpCode::Greater,
+        OpCode::Less,
+        OpCode::Negate,
+        OpCode::Not,
+        OpCode::Pop,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Return,
+    ];
+
+    /// The canonical `OP_*` name used by the disassembler, traces, and
+    /// error messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            OpCode::Constant => "OP_CONSTANT",
+            OpCode::Nil => "OP_NIL",
+            OpCode::True => "OP_TRUE",
+            OpCode::False => "OP_FALSE",
+            OpCode::Add => "OP_ADD",
+            OpCode::Subtract => "OP_SUBTRACT",
+            OpCode::Multiply => "OP_MULTIPLY",
+            OpCode::Divide => "OP_DIVIDE",
+            OpCode::Equal => "OP_EQUAL",
+            OpCode::Greater => "OP_GREATER",
+            OpCode::Less => "OP_LESS",
+            OpCode::Negate => "OP_NEGATE",
+            OpCode::Not => "OP_NOT",
+            OpCode::Pop => "OP_POP",
+            OpCode::Print => "OP_PRINT",
+            OpCode::Jump => "OP_JUMP",
+            OpCode::JumpIfFalse => "OP_JUMP_IF_FALSE",
+            OpCode::Return => "OP_RETURN",
+        }
+    }
+
+    /// Size of the instruction in bytes, operands included.
+    pub fn size(self) -> usize {
+        match self {
+            OpCode::Constant => 2,
+            OpCode::Jump | OpCode::JumpIfFalse => 3,
+            _ => 1,
+        }
+    }
+
+    /// Whether execution never continues to the next instruction.
+    pub fn is_terminator(self) -> bool {
+        matches!(self, OpCode::Jump | OpCode::Return)
+    }
+
+    /// Whether the instruction has a 16-bit forward offset as its operand.
+    pub fn is_jump(self) -> bool {
+        matches!(self, OpCode::Jump | OpCode::JumpIfFalse)
+    }
+
+    /// Net number of values the instruction pushes (positive) or pops
+    /// (negative).
+    pub fn stack_effect(self) -> isize {
+        match self {
+            OpCode::Constant | OpCode::Nil | OpCode::True | OpCode::False => 1,
+            OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Equal
+            | OpCode::Greater
+            | OpCode::Less => -1,
+            OpCode::Negate | OpCode::Not | OpCode::Jump | OpCode::JumpIfFalse => 0,
+            OpCode::Pop | OpCode::Print | OpCode::Return => -1,
+        }
+    }
+
+    /// Number of values the instruction reads off the top of the stack.
+    pub fn stack_inputs(self) -> usize {
+        match self {
+            OpCode::Constant | OpCode::Nil | OpCode::True | OpCode::False | OpCode::Jump => 0,
+            OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Equal
+            | OpCode::Greater
+            | OpCode::Less => 2,
+            OpCode::Negate
+            | OpCode::Not
+            | OpCode::Pop
+            | OpCode::Print
+            | OpCode::JumpIfFalse
+            | OpCode::Return => 1,
+        }
+    }
+}
+
 impl From<OpCode> for u8 {
     fn from(op: OpCode) -> Self {
         op as u8
@@ -21,13 +140,61 @@ impl TryFrom<u8> for OpCode {
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
             x if x == OpCode::Constant as u8 => Ok(OpCode::Constant),
+            x if x == OpCode::Nil as u8 => Ok(OpCode::Nil),
+            x if x == OpCode::True as u8 => Ok(OpCode::True),
+            x if x == OpCode::False as u8 => Ok(OpCode::False),
             x if x == OpCode::Add as u8 => Ok(OpCode::Add),
             x if x == OpCode::Subtract as u8 => Ok(OpCode::Subtract),
             x if x == OpCode::Multiply as u8 => Ok(OpCode::Multiply),
             x if x == OpCode::Divide as u8 => Ok(OpCode::Divide),
+            x if x == OpCode::Equal as u8 => Ok(OpCode::Equal),
+            x if x == OpCode::Greater as u8 => Ok(OpCode::Greater),
+            x if x == OpCode::Less as u8 => Ok(OpCode::Less),
             x if x == OpCode::Negate as u8 => Ok(OpCode::Negate),
+            x if x == OpCode::Not as u8 => Ok(OpCode::Not),
+            x if x == OpCode::Pop as u8 => Ok(OpCode::Pop),
+            x if x == OpCode::Print as u8 => Ok(OpCode::Print),
+            x if x == OpCode::Jump as u8 => Ok(OpCode::Jump),
+            x if x == OpCode::JumpIfFalse as u8 => Ok(OpCode::JumpIfFalse),
             x if x == OpCode::Return as u8 => Ok(OpCode::Return),
             _ => Err(()),
         }
     }
 }
+
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // `pad` so width and alignment flags line up disassembly columns.
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for OpCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OpCode::ALL
+            .into_iter()
+            .find(|op| op.name() == s)
+            .ok_or_else(|| format!("Unknown opcode '{s}'."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for (byte, op) in OpCode::ALL.into_iter().enumerate() {
+            assert_eq!(OpCode::try_from(byte as u8), Ok(op));
+            assert_eq!(op.to_string().parse::<OpCode>(), Ok(op));
+        }
+        assert_eq!(OpCode::Constant.to_string(), "OP_CONSTANT");
+        assert_eq!(format!("{:<12}|", OpCode::Add), "OP_ADD      |");
+        assert_eq!(
+            "OP_NOPE".parse::<OpCode>(),
+            Err("Unknown opcode 'OP_NOPE'.".to_owned())
+        );
+    }
+}
@@ -0,0 +1,60 @@
+/// Capabilities and resource limits granted to a script.
+///
+/// The budgets are enforced by the VM while running. The `allow_*` flags
+/// only record what the host grants: the VM defines no natives yet, so
+/// nothing consults them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SandboxPolicy {
+    pub allow_fs: bool,
+    pub allow_env: bool,
+    pub allow_time: bool,
+    pub allow_random: bool,
+    /// Maximum number of instructions a single `interpret` call may execute.
+    pub instruction_budget: Option<u64>,
+    /// Maximum number of bytes the values on the stack may occupy, counting
+    /// the text of their strings.
+    pub memory_budget: Option<usize>,
+}
+
+impl SandboxPolicy {
+    /// Every capability granted and no budgets.
+    pub fn permissive() -> Self {
+        Self {
+            allow_fs: true,
+            allow_env: true,
+            allow_time: true,
+            allow_random: true,
+            instruction_budget: None,
+            memory_budget: None,
+        }
+    }
+
+    /// Every capability withheld and no budgets; suitable for untrusted
+    /// scripts once budgets are added.
+    pub fn pure() -> Self {
+        Self {
+            allow_fs: false,
+            allow_env: false,
+            allow_time: false,
+            allow_random: false,
+            instruction_budget: None,
+            memory_budget: None,
+        }
+    }
+
+    pub fn with_instruction_budget(mut self, budget: u64) -> Self {
+        self.instruction_budget = Some(budget);
+        self
+    }
+
+    pub fn with_memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+}
+
+impl Default for SandboxPolicy {
+    fn default() -> Self {
+        Self::permissive()
+    }
+}
@@ -1,4 +1,7 @@
-use crate::token::{Token, TokenKind};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::symbol::Interner;
+use crate::token::{Token, TokenKind, Trivia, TriviaKind, TriviaToken};
 
 #[derive(Debug)]
 pub struct Scanner {
@@ -6,15 +9,42 @@ pub struct Scanner {
     start: usize,
     current: usize,
     line: usize,
+    /// Column of the character at `current`, kept up to date as the
+    /// scanner advances so finding a token's column is constant time.
+    current_column: usize,
+    /// Column of the token being scanned.
+    column: usize,
+    /// Trivia skipped since the last token, when the caller wants it.
+    trivia: Option<Vec<Trivia>>,
+    /// Where identifiers get their symbols.
+    interner: Arc<Mutex<Interner>>,
 }
 
 impl Scanner {
+    /// A scanner with an interner of its own.
     pub fn new(source: &str) -> Self {
+        Self::with_interner(source, Arc::default())
+    }
+
+    /// A scanner that names identifiers in `interner`, so its symbols agree
+    /// with those of everything else sharing it.
+    pub fn with_interner(source: &str, interner: Arc<Mutex<Interner>>) -> Self {
+        // Editors on Windows often save UTF-8 with a byte order mark. It is
+        // only meaningful as the very first character of a file.
+        let start = if source.starts_with('\u{FEFF}') {
+            '\u{FEFF}'.len_utf8()
+        } else {
+            0
+        };
         Self {
             source: source.to_owned(),
-            start: 0,
-            current: 0,
+            start,
+            current: start,
             line: 1,
+            current_column: 1,
+            column: 1,
+            trivia: None,
+            interner,
         }
     }
 
@@ -28,6 +58,7 @@ impl Scanner {
         }
         let c = self.source[self.current..].chars().next().unwrap();
         self.current += c.len_utf8();
+        self.current_column += 1;
         Some(c)
     }
 
@@ -54,35 +85,102 @@ impl Scanner {
         }
     }
 
+    /// Counts the line break `c` that was just consumed. `\r\n` counts once,
+    /// at the `\n`; a lone `\r` is a line break of its own.
+    fn line_break(&mut self, c: char) {
+        if c == '\n' || (c == '\r' && self.peek() != Some('\n')) {
+            self.line += 1;
+            self.current_column = 1;
+        }
+    }
+
     fn lexeme(&self) -> &str {
         &self.source[self.start..self.current]
     }
 
-    // Example use inside string():
+    /// Scans a string literal, decoding `\u{...}` escapes. The token keeps
+    /// its quotes but holds the decoded text. Any other backslash is an
+    /// ordinary character.
     fn string(&mut self) -> Token {
+        let open_line = self.line;
+        let mut text = String::from('"');
+        let mut invalid_escape = None;
         while let Some(c) = self.peek() {
             if c == '"' {
                 break;
             }
-            if c == '\n' {
-                self.line += 1;
-            }
+            let escape_start = (self.current, self.current_column);
             self.advance();
+            if c == '\\' && self.peek() == Some('u') && self.peek_next() == Some('{') {
+                match self.unicode_escape() {
+                    Some(decoded) => text.push(decoded),
+                    None if invalid_escape.is_none() => {
+                        invalid_escape = Some(escape_start);
+                    }
+                    None => {}
+                }
+                continue;
+            }
+            text.push(c);
+            self.line_break(c);
         }
 
         if self.is_at_end() {
+            // Report the opening quote and resume scanning on the line after
+            // it, so one missing quote doesn't swallow the rest of the file.
+            self.line = open_line;
+            self.current = self.start + 1;
+            self.current_column = self.column + 1;
+            while self.peek().is_some_and(|c| c != '\n' && c != '\r') {
+                self.advance();
+            }
             return self.error_token("Unterminated string.");
         }
 
         self.advance(); // consume closing quote
 
-        let lexeme = self.lexeme().to_owned();
-        self.make_token(TokenKind::String(lexeme))
+        if let Some((start, column)) = invalid_escape {
+            let escape: String = self.source[start..]
+                .chars()
+                .take_while(|&c| c != '}' && c != '"')
+                .chain(Some('}'))
+                .collect();
+            let mut token = self.error_token(&format!("Invalid unicode escape '{escape}'."));
+            token.column = column;
+            return token;
+        }
+
+        text.push('"');
+        self.make_token(TokenKind::String(text))
+    }
+
+    /// Consumes the `u{...}` of an escape whose backslash was just consumed,
+    /// returning the character it names. Stops at a closing quote or line
+    /// break so a malformed escape cannot swallow the rest of the file.
+    fn unicode_escape(&mut self) -> Option<char> {
+        self.advance(); // u
+        self.advance(); // {
+        let digits_start = self.current;
+        while let Some(c) = self.peek() {
+            if matches!(c, '}' | '"' | '\n' | '\r') {
+                break;
+            }
+            self.advance();
+        }
+        let digits_end = self.current;
+        if !self.match_byte('}') || !(1..=6).contains(&(digits_end - digits_start)) {
+            return None;
+        }
+        let digits = &self.source[digits_start..digits_end];
+        u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(char::from_u32)
     }
 
     pub fn scan_token(&mut self) -> Token {
         self.skip_whitespace();
         self.start = self.current;
+        self.column = self.current_column;
 
         if self.is_at_end() {
             return self.make_token(TokenKind::Eof);
@@ -151,6 +249,7 @@ impl Scanner {
         Token {
             kind,
             line: self.line,
+            column: self.column,
         }
     }
 
@@ -158,28 +257,34 @@ impl Scanner {
         Token {
             kind: TokenKind::Error(message.to_string()),
             line: self.line,
+            column: self.column,
         }
     }
 
     fn skip_whitespace(&mut self) {
         loop {
+            let start = self.current;
             match self.peek() {
-                Some(' ' | '\r' | '\t') => {
+                // Vertical tab and form feed are whitespace, as in C.
+                Some(' ' | '\t' | '\x0b' | '\x0c') => {
                     self.advance();
+                    self.record_trivia(TriviaKind::Whitespace, start);
                 }
-                Some('\n') => {
-                    self.line += 1;
+                Some(c @ ('\n' | '\r')) => {
                     self.advance();
+                    self.line_break(c);
+                    self.record_trivia(TriviaKind::Newline, start);
                 }
                 Some('/') => {
                     if self.peek_next() == Some('/') {
                         // Skip comment until newline or end-of-input
                         while let Some(c) = self.peek() {
-                            if c == '\n' {
+                            if c == '\n' || c == '\r' {
                                 break;
                             }
                             self.advance();
                         }
+                        self.record_trivia(TriviaKind::Comment, start);
                     } else {
                         break;
                     }
@@ -189,6 +294,28 @@ impl Scanner {
         }
     }
 
+    /// Keeps the skipped text from `start`, if trivia is wanted, merging
+    /// runs of whitespace and the halves of a `\r\n`.
+    fn record_trivia(&mut self, kind: TriviaKind, start: usize) {
+        let Some(trivia) = &mut self.trivia else {
+            return;
+        };
+        let text = &self.source[start..self.current];
+        match trivia.last_mut() {
+            Some(last)
+                if last.kind == kind
+                    && (kind == TriviaKind::Whitespace
+                        || (kind == TriviaKind::Newline && last.text == "\r" && text == "\n")) =>
+            {
+                last.text.push_str(text)
+            }
+            _ => trivia.push(Trivia {
+                kind,
+                text: text.to_owned(),
+            }),
+        }
+    }
+
     fn number(&mut self) -> Token {
         // Consume the integer part
         while let Some(b) = self.peek() {
@@ -212,11 +339,7 @@ impl Scanner {
         }
 
         // TODO: Consider converting to `f64` here instead of storing the owned `String`
-        let lexeme = &self.source[self.start..self.current];
-        Token {
-            kind: TokenKind::Number(lexeme.to_owned()),
-            line: self.line,
-        }
+        self.make_token(TokenKind::Number(self.lexeme().to_owned()))
     }
 
     fn is_alpha(c: char) -> bool {
@@ -235,25 +358,108 @@ impl Scanner {
         self.make_token(kind)
     }
 
+    /// Classifies an identifier the way clox does: the first one or two
+    /// characters pick the only keyword it could be, and just the remaining
+    /// characters are compared.
     fn identifier_type(&self, lexeme: &str) -> TokenKind {
-        match lexeme {
-            "and" => TokenKind::And,
-            "class" => TokenKind::Class,
-            "else" => TokenKind::Else,
-            "false" => TokenKind::False,
-            "for" => TokenKind::For,
-            "fun" => TokenKind::Fun,
-            "if" => TokenKind::If,
-            "nil" => TokenKind::Nil,
-            "or" => TokenKind::Or,
-            "print" => TokenKind::Print,
-            "return" => TokenKind::Return,
-            "super" => TokenKind::Super,
-            "this" => TokenKind::This,
-            "true" => TokenKind::True,
-            "var" => TokenKind::Var,
-            "while" => TokenKind::While,
-            _ => TokenKind::Identifier(lexeme.to_owned()),
+        let bytes = lexeme.as_bytes();
+        let keyword = match bytes[0] {
+            b'a' => Self::check_keyword(lexeme, 1, "nd", TokenKind::And),
+            b'c' => Self::check_keyword(lexeme, 1, "lass", TokenKind::Class),
+            b'e' => Self::check_keyword(lexeme, 1, "lse", TokenKind::Else),
+            b'f' => match bytes.get(1) {
+                Some(b'a') => Self::check_keyword(lexeme, 2, "lse", TokenKind::False),
+                Some(b'o') => Self::check_keyword(lexeme, 2, "r", TokenKind::For),
+                Some(b'u') => Self::check_keyword(lexeme, 2, "n", TokenKind::Fun),
+                _ => None,
+            },
+            b'i' => Self::check_keyword(lexeme, 1, "f", TokenKind::If),
+            b'n' => Self::check_keyword(lexeme, 1, "il", TokenKind::Nil),
+            b'o' => Self::check_keyword(lexeme, 1, "r", TokenKind::Or),
+            b'p' => Self::check_keyword(lexeme, 1, "rint", TokenKind::Print),
+            b'r' => Self::check_keyword(lexeme, 1, "eturn", TokenKind::Return),
+            b's' => Self::check_keyword(lexeme, 1, "uper", TokenKind::Super),
+            b't' => match bytes.get(1) {
+                Some(b'h') => Self::check_keyword(lexeme, 2, "is", TokenKind::This),
+                Some(b'r') => Self::check_keyword(lexeme, 2, "ue", TokenKind::True),
+                _ => None,
+            },
+            b'v' => Self::check_keyword(lexeme, 1, "ar", TokenKind::Var),
+            b'w' => Self::check_keyword(lexeme, 1, "hile", TokenKind::While),
+            _ => None,
+        };
+        keyword.unwrap_or_else(|| {
+            // The table is never left half-updated, so a poisoned lock is
+            // usable.
+            let mut interner = self.interner.lock().unwrap_or_else(PoisonError::into_inner);
+            TokenKind::Identifier(interner.intern(lexeme))
+        })
+    }
+
+    fn check_keyword(lexeme: &str, start: usize, rest: &str, kind: TokenKind) -> Option<TokenKind> {
+        (lexeme.get(start..) == Some(rest)).then_some(kind)
+    }
+}
+
+/// Whether `source` stops partway through an expression: inside an
+/// unterminated string or with brackets left open. The REPL uses this to
+/// keep reading lines instead of reporting an error.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut scanner = Scanner::new(source);
+    let mut depth = 0usize;
+    loop {
+        let token = scanner.scan_token();
+        match token.kind {
+            TokenKind::LeftParen | TokenKind::LeftBrace => depth += 1,
+            TokenKind::RightParen | TokenKind::RightBrace => {
+                if depth == 0 {
+                    // Unbalanced the other way; more input cannot fix it.
+                    return false;
+                }
+                depth -= 1;
+            }
+            TokenKind::Error(ref message) if message == "Unterminated string." => return true,
+            TokenKind::Eof => return depth > 0,
+            _ => {}
+        }
+    }
+}
+
+/// Scans all of `source`, keeping the whitespace and comments the compiler
+/// skips. Formatters and documentation tools use this to reproduce the
+/// source exactly.
+pub fn scan_with_trivia(source: &str) -> Vec<TriviaToken> {
+    let mut scanner = Scanner::new(source);
+    let mut leading = Vec::new();
+    if scanner.start > 0 {
+        leading.push(Trivia {
+            kind: TriviaKind::ByteOrderMark,
+            text: source[..scanner.start].to_owned(),
+        });
+    }
+    scanner.trivia = Some(leading);
+
+    let mut tokens: Vec<TriviaToken> = Vec::new();
+    loop {
+        let token = scanner.scan_token();
+        let mut leading = scanner.trivia.replace(Vec::new()).unwrap_or_default();
+        if let Some(previous) = tokens.last_mut() {
+            // What follows a token on its own line belongs to it.
+            let end = leading
+                .iter()
+                .position(|trivia| trivia.kind == TriviaKind::Newline)
+                .unwrap_or(leading.len());
+            previous.trailing = leading.drain(..end).collect();
+        }
+        let at_end = token.kind == TokenKind::Eof;
+        tokens.push(TriviaToken {
+            leading,
+            text: scanner.lexeme().to_owned(),
+            token,
+            trailing: Vec::new(),
+        });
+        if at_end {
+            return tokens;
         }
     }
 }
@@ -261,6 +467,56 @@ impl Scanner {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::symbol::Symbol;
+
+    fn name(scanner: &Scanner, name: &str) -> Symbol {
+        scanner.interner.lock().unwrap().intern(name)
+    }
+
+    #[test]
+    fn trivia_reproduces_source() {
+        let source = "\u{FEFF}// lead\n1 +  \"a\\u{41}\" // add\r\n\t-2 ";
+        let tokens = scan_with_trivia(source);
+        let rebuilt: String = tokens
+            .iter()
+            .flat_map(|t| {
+                let leading = t.leading.iter().map(|trivia| trivia.text.as_str());
+                let trailing = t.trailing.iter().map(|trivia| trivia.text.as_str());
+                leading.chain(Some(t.text.as_str())).chain(trailing)
+            })
+            .collect();
+        assert_eq!(rebuilt, source);
+
+        let kinds = |trivia: &[Trivia]| trivia.iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds(&tokens[0].leading),
+            [
+                TriviaKind::ByteOrderMark,
+                TriviaKind::Comment,
+                TriviaKind::Newline
+            ]
+        );
+        assert_eq!(tokens[2].text, "\"a\\u{41}\"");
+        assert_eq!(
+            kinds(&tokens[2].trailing),
+            [TriviaKind::Whitespace, TriviaKind::Comment]
+        );
+        assert_eq!(tokens[3].leading[0].text, "\r\n");
+        assert_eq!(tokens[4].trailing[0].text, " ");
+        assert_eq!(tokens[5].token.kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn incomplete_input() {
+        assert!(!is_incomplete("1 + 2"));
+        assert!(!is_incomplete(""));
+        assert!(is_incomplete("(1 +"));
+        assert!(is_incomplete("((1) + 2"));
+        assert!(is_incomplete("\"multi"));
+        assert!(!is_incomplete("(1 + \"(\")"));
+        assert!(!is_incomplete("1 + 2) + ("));
+        assert!(!is_incomplete("1 // (comment"));
+    }
 
     #[test]
     fn identifiers() {
@@ -268,21 +524,25 @@ mod tests {
 abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890_"#;
         let mut scanner = Scanner::new(source);
         let expected_tokens = vec![
-            Token::identifier("andy", 1),
-            Token::identifier("formless", 1),
-            Token::identifier("fo", 1),
-            Token::identifier("_", 1),
-            Token::identifier("_123", 1),
-            Token::identifier("_abc", 1),
-            Token::identifier("ab123", 1),
+            Token::identifier(name(&scanner, "andy"), 1),
+            Token::identifier(name(&scanner, "formless"), 1),
+            Token::identifier(name(&scanner, "fo"), 1),
+            Token::identifier(name(&scanner, "_"), 1),
+            Token::identifier(name(&scanner, "_123"), 1),
+            Token::identifier(name(&scanner, "_abc"), 1),
+            Token::identifier(name(&scanner, "ab123"), 1),
             Token::identifier(
-                "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890_",
+                name(
+                    &scanner,
+                    "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890_",
+                ),
                 2,
             ),
             Token::new(TokenKind::Eof, 2),
         ];
         for expected in expected_tokens {
-            assert_eq!(scanner.scan_token(), expected);
+            let token = scanner.scan_token();
+            assert_eq!((token.kind, token.line), (expected.kind, expected.line));
         }
     }
 
@@ -309,7 +569,24 @@ abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890_"#;
             Token::new(TokenKind::Eof, 1),
         ];
         for expected in expected_tokens {
-            assert_eq!(scanner.scan_token(), expected);
+            let token = scanner.scan_token();
+            assert_eq!((token.kind, token.line), (expected.kind, expected.line));
+        }
+    }
+
+    #[test]
+    fn keyword_lookalikes() {
+        let source =
+            "a an andd f fa fals fo fu funny i n o p prints t th tr thiss v w printer print";
+        let mut scanner = Scanner::new(source);
+        for lexeme in source.split(' ') {
+            let token = scanner.scan_token();
+            let expected = if lexeme == "print" {
+                TokenKind::Print
+            } else {
+                TokenKind::Identifier(name(&scanner, lexeme))
+            };
+            assert_eq!(token.kind, expected);
         }
     }
 
@@ -330,7 +607,8 @@ abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890_"#;
             Token::new(TokenKind::Eof, 4),
         ];
         for expected in expected_tokens {
-            assert_eq!(scanner.scan_token(), expected);
+            let token = scanner.scan_token();
+            assert_eq!((token.kind, token.line), (expected.kind, expected.line));
         }
     }
 
@@ -360,7 +638,8 @@ abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890_"#;
             Token::new(TokenKind::Eof, 1),
         ];
         for expected in expected_tokens {
-            assert_eq!(scanner.scan_token(), expected);
+            let token = scanner.scan_token();
+            assert_eq!((token.kind, token.line), (expected.kind, expected.line));
         }
     }
 
@@ -375,7 +654,8 @@ abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890_"#;
             Token::new(TokenKind::Eof, 2),
         ];
         for expected in expected_tokens {
-            assert_eq!(scanner.scan_token(), expected);
+            let token = scanner.scan_token();
+            assert_eq!((token.kind, token.line), (expected.kind, expected.line));
         }
     }
 
@@ -389,14 +669,15 @@ abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890_"#;
 end"#;
         let mut scanner = Scanner::new(source);
         let expected_tokens = vec![
-            Token::identifier("space", 1),
-            Token::identifier("tabs", 1),
-            Token::identifier("newlines", 1),
-            Token::identifier("end", 6),
+            Token::identifier(name(&scanner, "space"), 1),
+            Token::identifier(name(&scanner, "tabs"), 1),
+            Token::identifier(name(&scanner, "newlines"), 1),
+            Token::identifier(name(&scanner, "end"), 6),
             Token::new(TokenKind::Eof, 6),
         ];
         for expected in expected_tokens {
-            assert_eq!(scanner.scan_token(), expected);
+            let token = scanner.scan_token();
+            assert_eq!((token.kind, token.line), (expected.kind, expected.line));
         }
     }
 
@@ -408,4 +689,210 @@ end"#;
             println!("{:?}", scanner.scan_token());
         }
     }
+
+    #[test]
+    fn line_endings() {
+        let source = "a\r\nb\rc\nd\r\n\r\ne\x0b\x0cf \"x\r\ny\" g // comment\rh";
+        let mut scanner = Scanner::new(source);
+        let expected = [
+            ("a", 1),
+            ("b", 2),
+            ("c", 3),
+            ("d", 4),
+            ("e", 6),
+            ("f", 6),
+            ("\"x\r\ny\"", 7),
+            ("g", 7),
+            ("h", 8),
+            ("", 8),
+        ];
+        for (lexeme, line) in expected {
+            let token = scanner.scan_token();
+            assert_eq!((token.lexeme(), token.line), (lexeme, line));
+        }
+    }
+
+    #[test]
+    fn columns() {
+        // A multi-line string takes its line from its end, as in clox, but
+        // its column from its start.
+        let source = "ab + 12\r\n\t(\"🦀\" x\n  \"a\nb\" c";
+        let mut scanner = Scanner::new(source);
+        let expected = [
+            (1, 1),
+            (1, 4),
+            (1, 6),
+            (2, 2),
+            (2, 3),
+            (2, 7),
+            (4, 3),
+            (4, 4),
+            (4, 5),
+        ];
+        for (line, column) in expected {
+            let token = scanner.scan_token();
+            assert_eq!((token.line, token.column), (line, column), "{token:?}");
+        }
+    }
+
+    #[test]
+    fn unterminated_string_recovery() {
+        let mut scanner = Scanner::new("1 \"abc\r\n+ 2\n  def");
+        let expected = [
+            (TokenKind::Number("1".to_owned()), 1, 1),
+            (TokenKind::Error("Unterminated string.".to_owned()), 1, 3),
+            (TokenKind::Plus, 2, 1),
+            (TokenKind::Number("2".to_owned()), 2, 3),
+            (TokenKind::Identifier(name(&scanner, "def")), 3, 3),
+            (TokenKind::Eof, 3, 6),
+        ];
+        for expected in expected {
+            let token = scanner.scan_token();
+            assert_eq!((token.kind, token.line, token.column), expected);
+        }
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        let mut scanner = Scanner::new(r#""\u{1F980} \u{41}\u{0}" "\d\u" "\u{}""#);
+        assert_eq!(
+            scanner.scan_token().kind,
+            TokenKind::String("\"🦀 A\0\"".to_owned())
+        );
+        assert_eq!(
+            scanner.scan_token().kind,
+            TokenKind::String(r#""\d\u""#.to_owned())
+        );
+        assert_eq!(
+            scanner.scan_token().kind,
+            TokenKind::Error("Invalid unicode escape '\\u{}'.".to_owned())
+        );
+    }
+
+    #[test]
+    fn invalid_unicode_escapes() {
+        let cases = [
+            (r#""ab \u{D800} c""#, r"\u{D800}", 5),
+            (r#""\u{110000}""#, r"\u{110000}", 2),
+            (r#""\u{0000041}""#, r"\u{0000041}", 2),
+            (r#""\u{xyz}""#, r"\u{xyz}", 2),
+            (r#""\u{41""#, r"\u{41}", 2),
+        ];
+        for (source, escape, column) in cases {
+            let mut scanner = Scanner::new(source);
+            let token = scanner.scan_token();
+            assert_eq!(
+                token.kind,
+                TokenKind::Error(format!("Invalid unicode escape '{escape}'.")),
+                "{source}"
+            );
+            assert_eq!(token.column, column, "{source}");
+            assert_eq!(scanner.scan_token().kind, TokenKind::Eof, "{source}");
+        }
+        // The escape is on the line before the one the string ends on.
+        let token = Scanner::new("\"ab\\u{zz}\nc\"").scan_token();
+        assert_eq!((token.line, token.column), (2, 4));
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let mut scanner = Scanner::new("\u{FEFF}foo \u{FEFF}");
+        let token = scanner.scan_token();
+        assert_eq!(token.kind, TokenKind::Identifier(name(&scanner, "foo")));
+        assert_eq!(token.column, 1);
+        let token = scanner.scan_token();
+        assert_eq!(
+            token.kind,
+            TokenKind::Error("Unexpected character.".to_owned())
+        );
+        assert_eq!(scanner.scan_token().kind, TokenKind::Eof);
+    }
+
+    /// Lines in `source`, counting `\r\n`, `\r`, and `\n` as one break each.
+    fn line_count(source: &str) -> usize {
+        source.replace("\r\n", "\n").matches(['\r', '\n']).count() + 1
+    }
+
+    /// Deterministic xorshift generator so failures are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.below(items.len())]
+        }
+    }
+
+    const FIXED: &[&str] = &[
+        "(", ")", "{", "}", ",", ".", "-", "+", ";", "/", "*", "!", "!=", "=", "==", ">", ">=",
+        "<", "<=", "and", "class", "else", "false", "for", "fun", "if", "nil", "or", "print",
+        "return", "super", "this", "true", "var", "while",
+    ];
+    const SEPARATORS: &[&str] = &[" ", "\t", "\r", "\n", "  \n\t", " // comment\n", "\n\n"];
+
+    fn random_lexeme(rng: &mut Rng) -> String {
+        const ALPHA: &[u8] = b"abcxyzABCXYZ_";
+        const ALNUM: &[u8] = b"abcxyzABCXYZ_0123456789";
+        match rng.below(4) {
+            0 => rng.pick(FIXED).to_owned(),
+            1 => {
+                let mut s = String::from(ALPHA[rng.below(ALPHA.len())] as char);
+                for _ in 0..rng.below(8) {
+                    s.push(ALNUM[rng.below(ALNUM.len())] as char);
+                }
+                s
+            }
+            2 => {
+                let mut s = rng.below(100_000).to_string();
+                if rng.below(2) == 0 {
+                    s.push_str(&format!(".{}", rng.below(1000)));
+                }
+                s
+            }
+            _ => {
+                let body: String = (0..rng.below(6))
+                    .map(|_| rng.pick(&["a", " ", "\n", "🦀", "+", "/"]))
+                    .collect();
+                format!("\"{body}\"")
+            }
+        }
+    }
+
+    #[test]
+    fn random_token_streams_round_trip() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..500 {
+            let mut source = String::new();
+            let mut expected = Vec::new();
+            let names = Arc::default();
+
+            for _ in 0..rng.below(30) {
+                source.push_str(rng.pick(SEPARATORS));
+                let lexeme = random_lexeme(&mut rng);
+                source.push_str(&lexeme);
+                let line = line_count(&source);
+                let token = Scanner::with_interner(&lexeme, Arc::clone(&names)).scan_token();
+                expected.push((token.kind, line));
+            }
+            source.push_str(rng.pick(SEPARATORS));
+            let line = line_count(&source);
+            expected.push((TokenKind::Eof, line));
+
+            let mut scanner = Scanner::with_interner(&source, names);
+            for (kind, line) in expected {
+                let token = scanner.scan_token();
+                assert_eq!((token.kind, token.line), (kind, line), "source: {source:?}");
+            }
+        }
+    }
 }
@@ -0,0 +1,128 @@
+//! The files a program was compiled from.
+//!
+//! A host registers each source it compiles, such as a script, an import,
+//! or a line typed at the REPL, and passes the [`SourceId`] to the compiler
+//! in [`CompileOptions::with_source`]. Chunks, diagnostics, and trace
+//! frames carry the id, so errors can name the right file when several are
+//! in play.
+//!
+//! [`CompileOptions::with_source`]: crate::compiler::CompileOptions::with_source
+
+use crate::error::{Diagnostic, RuntimeError};
+
+/// A handle to a file in a [`SourceMap`]. Ids are only meaningful to the
+/// map that handed them out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceId(u32);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    /// A path, or a made-up name such as `<repl:3>` for virtual files.
+    pub name: String,
+    pub text: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, text: impl Into<String>) -> SourceId {
+        let id = u32::try_from(self.files.len()).expect("too many source files");
+        self.files.push(SourceFile {
+            name: name.into(),
+            text: text.into(),
+        });
+        SourceId(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn get(&self, id: SourceId) -> Option<&SourceFile> {
+        self.files.get(id.0 as usize)
+    }
+
+    /// The diagnostic prefixed with the name of its file, if known.
+    pub fn render_diagnostic(&self, diagnostic: &Diagnostic) -> String {
+        match diagnostic.source.and_then(|id| self.get(id)) {
+            Some(file) => format!("{}: {diagnostic}", file.name),
+            None => diagnostic.to_string(),
+        }
+    }
+
+    /// Like [`RuntimeError::render`], against the file the error happened
+    /// in. Frames from other files are followed by their file's name.
+    pub fn render_runtime_error(&self, error: &RuntimeError) -> String {
+        let home = error.trace.first().and_then(|frame| frame.source);
+        let (name, text) = match home.and_then(|id| self.get(id)) {
+            Some(file) => (file.name.as_str(), file.text.as_str()),
+            None => ("<unknown>", ""),
+        };
+        error.render_with_files(name, text, |frame| {
+            frame
+                .source
+                .filter(|&id| Some(id) != home)
+                .and_then(|id| self.get(id))
+                .map(|file| file.name.as_str())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::TraceFrame;
+
+    #[test]
+    fn names_files_in_errors() {
+        let mut sources = SourceMap::new();
+        let main = sources.add("main.lox", "1 +\n-nil");
+        let lib = sources.add("lib.lox", "");
+        assert_eq!(sources.get(lib).unwrap().name, "lib.lox");
+
+        let frame = |source, line| TraceFrame {
+            function: None,
+            source,
+            line,
+        };
+        let error = RuntimeError::new(
+            "Operand must be a number.",
+            2,
+            vec![frame(Some(main), 2), frame(Some(lib), 7)],
+        )
+        .with_column(1);
+        assert_eq!(
+            sources.render_runtime_error(&error),
+            "main.lox:2:1: Operand must be a number.\n   2 | -nil\n     | ^\n\
+             [line 2] in script\n[line 7] in script (lib.lox)\n"
+        );
+
+        let mut diagnostic = Diagnostic {
+            line: 1,
+            column: 4,
+            lexeme: None,
+            message: "Expect expression.".to_owned(),
+            source: Some(main),
+        };
+        assert_eq!(
+            sources.render_diagnostic(&diagnostic),
+            "main.lox: [line 1] Error: Expect expression."
+        );
+        diagnostic.source = None;
+        assert_eq!(
+            sources.render_diagnostic(&diagnostic),
+            "[line 1] Error: Expect expression."
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Interned identifier names.
+//!
+//! An [`Interner`] stores every distinct name once and hands out [`Symbol`]
+//! handles for it, so comparing two names is an integer compare. Each VM
+//! owns its own interner and passes it to the compilers and scanners it
+//! runs; hosts that want several to agree on symbols share one explicitly.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// A handle to a name in an [`Interner`]. Symbols are only comparable with
+/// others from the interner that handed them out.
+#[derive(Clone)]
+pub struct Symbol {
+    id: u32,
+    /// Shared with the interner, so cloning a symbol never allocates.
+    name: Arc<str>,
+}
+
+impl Symbol {
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Symbol({:?})", self.as_str())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A table of names. It only grows, and its names are freed along with it
+/// and the last symbol that refers to them.
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: HashMap<Arc<str>, u32>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The symbol for `name`, allocating it on first use.
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some((name, &id)) = self.symbols.get_key_value(name) {
+            return Symbol {
+                id,
+                name: Arc::clone(name),
+            };
+        }
+        let id = u32::try_from(self.symbols.len()).expect("too many distinct identifiers");
+        let name: Arc<str> = name.into();
+        self.symbols.insert(Arc::clone(&name), id);
+        Symbol { id, name }
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interning() {
+        let mut interner = Interner::new();
+        let a = interner.intern("a");
+        let b = interner.intern("b");
+        assert_ne!(a, b);
+        assert_eq!(interner.intern("a"), a);
+        assert_eq!(interner.len(), 2);
+        assert_eq!(a.as_str(), "a");
+        assert_eq!(b.to_string(), "b");
+        assert_eq!(format!("{a:?}"), "Symbol(\"a\")");
+    }
+
+    #[test]
+    fn interners_are_independent() {
+        let mut first = Interner::new();
+        let mut second = Interner::new();
+        first.intern("a");
+        assert_eq!(second.intern("b").as_str(), "b");
+        assert_eq!(second.len(), 1);
+        assert!(Interner::new().is_empty());
+    }
+}
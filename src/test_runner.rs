@@ -0,0 +1,212 @@
+//! Runs `.lox` files against expectations written in their comments, using
+//! the conventions of the official Lox test suite:
+//!
+//! - `// expect: <output>` for each line the program prints,
+//! - `// expect runtime error: <message>` for a runtime error on that line,
+//! - `// [line N] Error...` or `// Error...` (meaning the comment's own line)
+//!   for each compile error.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::capture::SharedBuffer;
+use crate::vm::{Interpret, VM};
+
+/// What a test file says should happen when it runs.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Expectations {
+    pub output: Vec<String>,
+    pub compile_errors: Vec<String>,
+    /// The message and the line it is expected on.
+    pub runtime_error: Option<(String, usize)>,
+}
+
+impl Expectations {
+    pub fn parse(source: &str) -> Self {
+        let mut expectations = Self::default();
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let Some((_, comment)) = line.split_once("// ") else {
+                continue;
+            };
+            if let Some(output) = comment.strip_prefix("expect: ") {
+                expectations.output.push(output.to_owned());
+            } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+                expectations.runtime_error = Some((message.to_owned(), line_number));
+            } else if comment.starts_with("[line ") {
+                expectations.compile_errors.push(comment.to_owned());
+            } else if comment.starts_with("Error") {
+                expectations
+                    .compile_errors
+                    .push(format!("[line {line_number}] {comment}"));
+            }
+        }
+        expectations
+    }
+
+    fn expected_result(&self) -> &'static str {
+        if !self.compile_errors.is_empty() {
+            "CompileError"
+        } else if self.runtime_error.is_some() {
+            "RuntimeError"
+        } else {
+            "Ok"
+        }
+    }
+}
+
+/// The name of `result`'s variant, to compare against
+/// [`Expectations::expected_result`].
+fn outcome(result: &Interpret) -> &'static str {
+    match result {
+        Interpret::Ok => "Ok",
+        Interpret::CompileError(_) => "CompileError",
+        Interpret::RuntimeError(_) => "RuntimeError",
+        Interpret::Interrupted => "Interrupted",
+    }
+}
+
+/// Runs `source` and returns a description of every way it deviated from
+/// its expectations. An empty list means the test passed.
+pub fn check(source: &str) -> Vec<String> {
+    let expectations = Expectations::parse(source);
+    let out = SharedBuffer::new();
+    let mut vm = VM::new();
+    vm.set_output(Box::new(out.clone()));
+    let result = vm.interpret(source);
+
+    let mut failures = Vec::new();
+    let expected_result = expectations.expected_result();
+    if outcome(&result) != expected_result {
+        failures.push(format!(
+            "Expected {expected_result} but got {}.",
+            outcome(&result)
+        ));
+    }
+
+    let output = out.contents();
+    let actual: Vec<&str> = output.lines().collect();
+    for (index, expected) in expectations.output.iter().enumerate() {
+        match actual.get(index) {
+            Some(line) if line == expected => {}
+            Some(line) => failures.push(format!("Expected output '{expected}' but got '{line}'.")),
+            None => failures.push(format!("Missing expected output '{expected}'.")),
+        }
+    }
+    for line in actual.iter().skip(expectations.output.len()) {
+        failures.push(format!("Got unexpected output '{line}'."));
+    }
+
+    let errors = result.error_report();
+    let error_lines: Vec<&str> = errors.lines().collect();
+    if let Some((message, line)) = &expectations.runtime_error {
+        if error_lines.first() != Some(&message.as_str()) {
+            failures.push(format!("Expected runtime error '{message}'."));
+        }
+        let trace = format!("[line {line}]");
+        if !error_lines.get(1).is_some_and(|l| l.starts_with(&trace)) {
+            failures.push(format!("Expected runtime error on line {line}."));
+        }
+    } else if matches!(result, Interpret::CompileError(_))
+        || !expectations.compile_errors.is_empty()
+    {
+        if error_lines != expectations.compile_errors {
+            failures.push(format!(
+                "Expected compile errors {:?} but got {error_lines:?}.",
+                expectations.compile_errors
+            ));
+        }
+    } else if !error_lines.is_empty() {
+        failures.push(format!("Got unexpected errors {error_lines:?}."));
+    }
+
+    failures
+}
+
+/// The outcome of running a set of test files.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub passed: usize,
+    pub failed: Vec<(PathBuf, Vec<String>)>,
+}
+
+impl Summary {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Runs a single `.lox` file, or recursively every `.lox` file under a
+/// directory, in path order.
+pub fn run_path(path: &Path) -> io::Result<Summary> {
+    let mut summary = Summary::default();
+    for path in lox_files(path)? {
+        let source = fs::read_to_string(&path)?;
+        let failures = check(&source);
+        if failures.is_empty() {
+            summary.passed += 1;
+        } else {
+            summary.failed.push((path, failures));
+        }
+    }
+    Ok(summary)
+}
+
+fn lox_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(lox_files(&path)?);
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_expectations() {
+        let source = "1 // expect: 1\n// Error 'x': Oops.\n// [line 7] Error: Bad.\n\
+                      // expect runtime error: Boom.";
+        let expectations = Expectations::parse(source);
+        assert_eq!(expectations.output, vec!["1"]);
+        assert_eq!(
+            expectations.compile_errors,
+            vec!["[line 2] Error 'x': Oops.", "[line 7] Error: Bad."]
+        );
+        assert_eq!(expectations.runtime_error, Some(("Boom.".to_owned(), 4)));
+    }
+
+    #[test]
+    fn passing() {
+        assert!(check("print 1 + 2; // expect: 3").is_empty());
+        assert!(check("print 1 + // Error '': Expect expression.").is_empty());
+    }
+
+    #[test]
+    fn failing() {
+        assert_eq!(
+            check("print 1 + 2; // expect: 4"),
+            vec!["Expected output '4' but got '3'."]
+        );
+        assert_eq!(check("print 1 + 2;"), vec!["Got unexpected output '3'."]);
+        assert_eq!(
+            check("print 1 + // expect: 1"),
+            vec![
+                "Expected Ok but got CompileError.",
+                "Missing expected output '1'.",
+                "Expected compile errors [] but got [\"[line 1] Error '': Expect expression.\"]."
+            ]
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt;
 
-use crate::value::Value;
+use crate::symbol::Symbol;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
@@ -26,7 +26,7 @@ pub enum TokenKind {
     Less,
     LessEqual,
     // Literals.
-    Identifier(String),
+    Identifier(Symbol),
     String(String),
     Number(String),
     // Keywords.
@@ -55,20 +55,27 @@ pub enum TokenKind {
 pub struct Token {
     pub kind: TokenKind,
     pub line: usize,
+    /// 1-based column of the token's first character on the line it starts
+    /// on, counted in characters, or 0 for tokens that did not come from the
+    /// scanner. A string spanning several lines has the `line` it ends on,
+    /// as in clox, so its column is on an earlier line than `line`. An
+    /// invalid escape error has the column of the escape instead.
+    pub column: usize,
 }
 
 impl Token {
     pub fn new(kind: TokenKind, line: usize) -> Self {
-        Self { kind, line }
-    }
-
-    pub fn identifier(identifier: &str, line: usize) -> Self {
         Self {
-            kind: TokenKind::Identifier(identifier.to_owned()),
+            kind,
             line,
+            column: 0,
         }
     }
 
+    pub fn identifier(symbol: Symbol, line: usize) -> Self {
+        Self::new(TokenKind::Identifier(symbol), line)
+    }
+
     pub fn lexeme(&self) -> &str {
         match self.kind {
             TokenKind::LeftParen => "(",
@@ -90,7 +97,7 @@ impl Token {
             TokenKind::GreaterEqual => ">=",
             TokenKind::Less => "<",
             TokenKind::LessEqual => "<=",
-            TokenKind::Identifier(ref s) => s,
+            TokenKind::Identifier(ref symbol) => symbol.as_str(),
             TokenKind::String(ref s) => s,
             TokenKind::Number(ref s) => s,
             TokenKind::And => "and",
@@ -115,13 +122,49 @@ impl Token {
     }
 }
 
+/// Source text the compiler skips between tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// Spaces, tabs, vertical tabs, and form feeds.
+    Whitespace,
+    /// One line break: `\n`, `\r\n`, or a lone `\r`.
+    Newline,
+    /// A `//` comment, without its line break.
+    Comment,
+    /// A byte order mark at the start of the file.
+    ByteOrderMark,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+}
+
+/// A token with the exact source text it came from and the trivia around
+/// it, from [`scan_with_trivia`](crate::scanner::scan_with_trivia).
+/// Concatenating `leading`, `text`, and `trailing` for every token gives
+/// back the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriviaToken {
+    /// Trivia since the previous token's trailing trivia.
+    pub leading: Vec<Trivia>,
+    pub token: Token,
+    /// The token's source text. Unlike [`Token::lexeme`], string escapes
+    /// are left undecoded.
+    pub text: String,
+    /// Trivia after the token up to, but not including, the next line
+    /// break.
+    pub trailing: Vec<Trivia>,
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.kind {
             TokenKind::Identifier(s) => write!(f, "IDENTIFIER {} null", s),
             TokenKind::String(s) => write!(f, "STRING {s} {}", &s[1..s.len() - 1]),
             TokenKind::Number(s) => {
-                let value: Value = s.parse().expect("failed to parse number");
+                let value: f64 = s.parse().expect("failed to parse number");
                 if value == value.trunc() {
                     write!(f, "NUMBER {s} {value}.0")
                 } else {
@@ -172,15 +215,17 @@ impl fmt::Display for Token {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::symbol::Interner;
 
     #[test]
     fn identifiers() {
+        let mut names = Interner::new();
         assert_eq!(
-            Token::identifier("andy", 1).to_string(),
+            Token::identifier(names.intern("andy"), 1).to_string(),
             "IDENTIFIER andy null"
         );
         assert_eq!(
-            Token::identifier("formless", 1).to_string(),
+            Token::identifier(names.intern("formless"), 1).to_string(),
             "IDENTIFIER formless null"
         );
     }
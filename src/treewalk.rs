@@ -0,0 +1,284 @@
+//! A jlox-style evaluator over the AST, kept as a reference implementation
+//! for checking the bytecode VM against.
+
+use std::io::{self, Write};
+
+use crate::ast::{self, Expr, Stmt};
+use crate::error;
+use crate::token::{Token, TokenKind};
+use crate::value::Value;
+use crate::vm::Interpret;
+
+/// A runtime failure, attributed to the operator token that caused it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub token: Token,
+    pub message: String,
+}
+
+pub struct Interpreter {
+    out: Box<dyn Write + Send>,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self {
+            out: Box::new(io::stdout()),
+        }
+    }
+
+    /// Redirects script output away from stdout.
+    pub fn set_output(&mut self, out: Box<dyn Write + Send>) {
+        self.out = out;
+    }
+
+    pub fn interpret(&mut self, source: &str) -> Interpret {
+        let statements = match ast::parse(source) {
+            Ok(statements) => statements,
+            Err(errors) => return Interpret::CompileError(errors),
+        };
+
+        for statement in &statements {
+            if let Err(error) = self.execute(statement) {
+                return Interpret::RuntimeError(
+                    error::RuntimeError::in_script(error.message, error.token.line)
+                        .with_column(error.token.column),
+                );
+            }
+        }
+        Interpret::Ok
+    }
+
+    pub fn execute(&mut self, statement: &Stmt) -> Result<(), RuntimeError> {
+        match statement {
+            Stmt::Expression(expr) => {
+                self.evaluate(expr)?;
+            }
+            Stmt::Print(expr) => {
+                let value = self.evaluate(expr)?;
+                let _ = writeln!(self.out, "{}", value);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if !self.evaluate(condition)?.is_falsey() {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Grouping(expr) => self.evaluate(expr),
+            Expr::Unary { operator, right } => {
+                let right = self.evaluate(right)?;
+                match operator.kind {
+                    TokenKind::Bang => Ok(Value::Bool(right.is_falsey())),
+                    TokenKind::Minus => Ok(Value::Number(-number_operand(operator, right)?)),
+                    _ => unreachable!(),
+                }
+            }
+            Expr::Binary { .. } | Expr::Logical { .. } => {
+                // Walk left-associative chains with a loop, so their
+                // length is not limited by the native stack.
+                let (spine, bottom) = expr.left_spine();
+                let mut value = self.evaluate(bottom)?;
+                for expr in spine.into_iter().rev() {
+                    value = match expr {
+                        Expr::Binary {
+                            operator, right, ..
+                        } => {
+                            let right = self.evaluate(right)?;
+                            binary(operator, value, right)?
+                        }
+                        Expr::Logical {
+                            operator, right, ..
+                        } => {
+                            // The deciding operand is the result, not a
+                            // boolean.
+                            let decided = match operator.kind {
+                                TokenKind::Or => !value.is_falsey(),
+                                TokenKind::And => value.is_falsey(),
+                                _ => unreachable!(),
+                            };
+                            if decided {
+                                value
+                            } else {
+                                self.evaluate(right)?
+                            }
+                        }
+                        _ => unreachable!(),
+                    };
+                }
+                Ok(value)
+            }
+        }
+    }
+}
+
+fn binary(operator: &Token, left: Value, right: Value) -> Result<Value, RuntimeError> {
+    match operator.kind {
+        TokenKind::Comma => return Ok(right),
+        TokenKind::Plus => return add(operator, &left, &right),
+        TokenKind::EqualEqual => return Ok(Value::Bool(left == right)),
+        TokenKind::BangEqual => return Ok(Value::Bool(left != right)),
+        _ => {}
+    }
+    let (a, b) = number_operands(operator, left, right)?;
+    match operator.kind {
+        TokenKind::Minus => Ok(Value::Number(a - b)),
+        TokenKind::Star => Ok(Value::Number(a * b)),
+        TokenKind::Slash => Ok(Value::Number(a / b)),
+        TokenKind::Greater => Ok(Value::Bool(a > b)),
+        TokenKind::GreaterEqual => Ok(Value::Bool(a >= b)),
+        TokenKind::Less => Ok(Value::Bool(a < b)),
+        TokenKind::LessEqual => Ok(Value::Bool(a <= b)),
+        _ => unreachable!(),
+    }
+}
+
+/// Adds two numbers or concatenates two strings, as `OP_ADD` does.
+fn add(operator: &Token, left: &Value, right: &Value) -> Result<Value, RuntimeError> {
+    if let (Value::Number(a), Value::Number(b)) = (left, right) {
+        return Ok(Value::Number(a + b));
+    }
+    match (left.as_str(), right.as_str()) {
+        (Some(a), Some(b)) => Ok(Value::string([a, b].concat())),
+        _ => Err(RuntimeError {
+            token: operator.clone(),
+            message: "Operands must be two numbers or two strings.".to_owned(),
+        }),
+    }
+}
+
+fn number_operand(operator: &Token, operand: Value) -> Result<f64, RuntimeError> {
+    operand.as_number().ok_or_else(|| RuntimeError {
+        token: operator.clone(),
+        message: "Operand must be a number.".to_owned(),
+    })
+}
+
+fn number_operands(
+    operator: &Token,
+    left: Value,
+    right: Value,
+) -> Result<(f64, f64), RuntimeError> {
+    match (left, right) {
+        (Value::Number(a), Value::Number(b)) => Ok((a, b)),
+        _ => Err(RuntimeError {
+            token: operator.clone(),
+            message: "Operands must be numbers.".to_owned(),
+        }),
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::SharedBuffer;
+
+    fn run(source: &str) -> (Interpret, String) {
+        let out = SharedBuffer::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(out.clone()));
+        let result = interpreter.interpret(source);
+        (result, out.contents())
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(run("print 1 + 2 * 3;"), (Interpret::Ok, "7\n".into()));
+        assert_eq!(run("print -(1 - 3) / 4;"), (Interpret::Ok, "0.5\n".into()));
+    }
+
+    #[test]
+    fn literals_and_not() {
+        assert_eq!(run("print !nil == true;"), (Interpret::Ok, "true\n".into()));
+        assert_eq!(run("print !0;"), (Interpret::Ok, "false\n".into()));
+        assert_eq!(run("print nil, false;"), (Interpret::Ok, "false\n".into()));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(run("print 1 + 1 == 2;"), (Interpret::Ok, "true\n".into()));
+        assert_eq!(
+            run("print 1 >= 2 != 2 <= 1;"),
+            (Interpret::Ok, "false\n".into())
+        );
+        assert_eq!(run("print \"a\" == 1;"), (Interpret::Ok, "false\n".into()));
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            run("print \"foo\" + \"bar\";"),
+            (Interpret::Ok, "foobar\n".into())
+        );
+        let (result, _) = run("print \"a\" + 1;");
+        let Interpret::RuntimeError(error) = result else {
+            panic!("expected a runtime error, got {result:?}");
+        };
+        assert_eq!(
+            error.message,
+            "Operands must be two numbers or two strings."
+        );
+    }
+
+    #[test]
+    fn statements() {
+        assert_eq!(
+            run("print 1;\n2;\nprint 3;"),
+            (Interpret::Ok, "1\n3\n".into())
+        );
+        let (result, out) = run("print 1;\n-nil;\nprint 2;");
+        assert!(matches!(result, Interpret::RuntimeError(_)));
+        assert_eq!(out, "1\n");
+    }
+
+    #[test]
+    fn control_flow() {
+        assert_eq!(
+            run("if (1 < 2) print \"yes\"; else print \"no\";\nif (nil) print 1;"),
+            (Interpret::Ok, "yes\n".into())
+        );
+        assert_eq!(
+            run("print nil or \"x\";\nprint 1 and 2;\nprint false and -nil;"),
+            (Interpret::Ok, "x\n2\nfalse\n".into())
+        );
+    }
+
+    #[test]
+    fn long_chains() {
+        let sum = format!("print 1{};", " + 1".repeat(10_000));
+        assert_eq!(run(&sum), (Interpret::Ok, "10001\n".into()));
+        let or = format!("print nil{} or 2;", " or false".repeat(10_000));
+        assert_eq!(run(&or), (Interpret::Ok, "2\n".into()));
+    }
+
+    #[test]
+    fn compile_error() {
+        let (result, out) = run("1 +");
+        let Interpret::CompileError(errors) = result else {
+            panic!("expected a compile error, got {result:?}");
+        };
+        assert_eq!(out, "");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error '': Expect expression."
+        );
+    }
+}
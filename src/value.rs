@@ -1,18 +1,198 @@
-pub type Value = f64;
+use std::fmt;
+use std::sync::Arc;
 
-pub fn print_value(value: &f64) {
-    if *value == 0.0 {
-        print!("0");
-        return;
+/// A Lox value. Heap objects live behind a reference count, so cloning a
+/// value never copies more than a pointer.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum Value {
+    #[default]
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Obj(Arc<Obj>),
+}
+
+/// A value that lives on the heap.
+#[derive(Debug, PartialEq)]
+pub enum Obj {
+    String(String),
+}
+
+impl Value {
+    pub fn string(s: impl Into<String>) -> Self {
+        Value::Obj(Arc::new(Obj::String(s.into())))
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Obj(obj) => match &**obj {
+                Obj::String(s) => Some(s),
+            },
+            _ => None,
+        }
+    }
+
+    /// `nil` and `false` are falsey; every other value is truthy.
+    pub fn is_falsey(&self) -> bool {
+        matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    /// The name of the value's type, as the REPL's `:type` shows it.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "Nil",
+            Value::Bool(_) => "Bool",
+            Value::Number(_) => "Number",
+            Value::Obj(obj) => match **obj {
+                Obj::String(_) => "String",
+            },
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+/// The value the way Lox prints it. Everything that shows a value to the
+/// user (the VM, the REPL, the disassembler, the debugger) goes through
+/// this.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nil => f.pad("nil"),
+            Value::Bool(b) => f.pad(if *b { "true" } else { "false" }),
+            Value::Number(n) => f.pad(&format_number(*n)),
+            Value::Obj(obj) => match &**obj {
+                Obj::String(s) => f.pad(s),
+            },
+        }
     }
+}
+
+/// Formats a number the way clox prints it with C's `%g`: six significant
+/// digits, trailing zeros removed, and scientific notation when the exponent
+/// is below -4 or at least 6.
+///
+/// NaN is always printed as `nan`; its sign bit depends on how the platform
+/// produced it, so printing it would make output non-portable.
+pub fn format_number(value: f64) -> String {
+    const PRECISION: i32 = 6;
+
+    if value.is_nan() {
+        return "nan".to_owned();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_owned();
+    }
+    if value == 0.0 {
+        return if value.is_sign_negative() { "-0" } else { "0" }.to_owned();
+    }
+
+    // The exponent after rounding to PRECISION significant digits decides
+    // between fixed and scientific notation, exactly as %g specifies.
+    let scientific = format!("{:.*e}", (PRECISION - 1) as usize, value);
+    let (mantissa, exponent) = scientific
+        .split_once('e')
+        .expect("scientific format has an exponent");
+    let exponent: i32 = exponent.parse().expect("exponent is an integer");
+
+    if (-4..PRECISION).contains(&exponent) {
+        let decimals = (PRECISION - 1 - exponent) as usize;
+        trim_fraction(&format!("{value:.decimals$}")).to_owned()
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{sign}{:02}", trim_fraction(mantissa), exponent.abs())
+    }
+}
 
-    if value.abs() >= 1e6 || value.abs() < 1e-4 {
-        // Use scientific notation for very large/small numbers (like C's %g)
-        print!("{:.6e}", value);
+/// Drops trailing zeros after the decimal point, and the point itself if
+/// nothing is left after it.
+fn trim_fraction(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
     } else {
-        // Regular fixed-point with trimming
-        let s = format!("{:.6}", value);
-        let trimmed = s.trim_end_matches('0').trim_end_matches('.');
-        print!("{trimmed}");
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_c_percent_g() {
+        let cases = [
+            (0.0, "0"),
+            (-0.0, "-0"),
+            (1.0, "1"),
+            (-2.5, "-2.5"),
+            (100.0, "100"),
+            (100000.0, "100000"),
+            (123456.7, "123457"),
+            (999999.0, "999999"),
+            (999999.5, "1e+06"),
+            (1000000.0, "1e+06"),
+            (123456789.0, "1.23457e+08"),
+            (1e100, "1e+100"),
+            (-1.5e300, "-1.5e+300"),
+            (0.0001, "0.0001"),
+            (0.00012345678, "0.000123457"),
+            (0.00001, "1e-05"),
+            (1.5e-7, "1.5e-07"),
+            (1.0 / 3.0, "0.333333"),
+            (2.0 / 3.0, "0.666667"),
+            (f64::INFINITY, "inf"),
+            (f64::NEG_INFINITY, "-inf"),
+            (f64::NAN, "nan"),
+            (-f64::NAN, "nan"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(format_number(value), expected, "formatting {value:?}");
+        }
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Value::Number(2.5).to_string(), "2.5");
+        assert_eq!(format!("[{:>5}]", Value::Number(1e6)), "[1e+06]");
+        assert_eq!(format!("[{:<4}]", Value::Number(1.0)), "[1   ]");
+        assert_eq!(format!("[{:<6}]", Value::Bool(true)), "[true  ]");
+        assert_eq!(Value::Nil.to_string(), "nil");
+        assert_eq!(Value::string("a\"b").to_string(), "a\"b");
+    }
+
+    #[test]
+    fn equality() {
+        assert_eq!(Value::Number(1.0), Value::from(1.0));
+        assert_ne!(Value::Number(0.0), Value::Bool(false));
+        assert_ne!(Value::Nil, Value::Bool(false));
+        assert_ne!(Value::Number(f64::NAN), Value::Number(f64::NAN));
+        assert_eq!(Value::string("ab"), Value::string(String::from("a") + "b"));
+        assert_ne!(Value::string("1"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn truthiness() {
+        assert!(Value::Nil.is_falsey());
+        assert!(Value::Bool(false).is_falsey());
+        assert!(!Value::Bool(true).is_falsey());
+        assert!(!Value::Number(0.0).is_falsey());
+        assert!(!Value::string("").is_falsey());
     }
 }
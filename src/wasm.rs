@@ -0,0 +1,214 @@
+//! Compiles a chunk to a standalone WebAssembly module.
+//!
+//! The module imports `env.print: (f64) -> ()` and exports `main: () -> ()`,
+//! which runs the script, handing each number it prints to `print`.
+//! Formatting the number is left to the host.
+
+use crate::backend;
+use crate::chunk::Chunk;
+use crate::opcode::OpCode;
+
+const MAGIC: &[u8] = b"\0asm";
+const VERSION: &[u8] = &[1, 0, 0, 0];
+
+const SECTION_TYPE: u8 = 1;
+const SECTION_IMPORT: u8 = 2;
+const SECTION_FUNCTION: u8 = 3;
+const SECTION_EXPORT: u8 = 7;
+const SECTION_CODE: u8 = 10;
+
+const TYPE_FUNC: u8 = 0x60;
+const TYPE_F64: u8 = 0x7c;
+const EXTERNAL_FUNC: u8 = 0x00;
+
+const I_RETURN: u8 = 0x0f;
+const I_CALL: u8 = 0x10;
+const I_DROP: u8 = 0x1a;
+const I_F64_CONST: u8 = 0x44;
+const I_F64_NEG: u8 = 0x9a;
+const I_F64_ADD: u8 = 0xa0;
+const I_F64_SUB: u8 = 0xa1;
+const I_F64_MUL: u8 = 0xa2;
+const I_F64_DIV: u8 = 0xa3;
+const I_END: u8 = 0x0b;
+
+/// Function index of the imported `print`.
+const PRINT: u32 = 0;
+
+/// Translates `chunk` into the bytes of a `.wasm` module, or explains why
+/// the chunk is not bytecode the backend can translate.
+pub fn compile(chunk: &Chunk) -> Result<Vec<u8>, String> {
+    backend::check_supported(chunk, "wasm")?;
+
+    let mut module = Vec::new();
+    module.extend_from_slice(MAGIC);
+    module.extend_from_slice(VERSION);
+
+    // Type 0 is `print`, type 1 is `main`.
+    let mut types = Vec::new();
+    write_u32(&mut types, 2);
+    types.extend_from_slice(&[TYPE_FUNC, 1, TYPE_F64, 0]);
+    types.extend_from_slice(&[TYPE_FUNC, 0, 0]);
+    section(&mut module, SECTION_TYPE, &types);
+
+    let mut imports = Vec::new();
+    write_u32(&mut imports, 1);
+    write_name(&mut imports, "env");
+    write_name(&mut imports, "print");
+    imports.extend_from_slice(&[EXTERNAL_FUNC, 0]);
+    section(&mut module, SECTION_IMPORT, &imports);
+
+    section(&mut module, SECTION_FUNCTION, &[1, 1]);
+
+    let mut exports = Vec::new();
+    write_u32(&mut exports, 1);
+    write_name(&mut exports, "main");
+    exports.push(EXTERNAL_FUNC);
+    write_u32(&mut exports, PRINT + 1);
+    section(&mut module, SECTION_EXPORT, &exports);
+
+    let mut body = vec![0]; // No locals.
+    body.extend(translate(chunk));
+    body.push(I_END);
+    let mut code = Vec::new();
+    write_u32(&mut code, 1);
+    write_u32(&mut code, body.len() as u32);
+    code.extend(body);
+    section(&mut module, SECTION_CODE, &code);
+
+    Ok(module)
+}
+
+/// Translates instructions up to the first return, which `verify` has
+/// guaranteed exists. The wasm operand stack stands in for the VM's.
+fn translate(chunk: &Chunk) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut offset = 0;
+    loop {
+        let op = OpCode::try_from(chunk.code[offset]).expect("chunk was verified");
+        offset += 1;
+        match op {
+            OpCode::Constant => {
+                let value = chunk.constants[chunk.code[offset] as usize]
+                    .as_number()
+                    .expect("constants were checked");
+                offset += 1;
+                out.push(I_F64_CONST);
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            OpCode::Add => out.push(I_F64_ADD),
+            OpCode::Subtract => out.push(I_F64_SUB),
+            OpCode::Multiply => out.push(I_F64_MUL),
+            OpCode::Divide => out.push(I_F64_DIV),
+            OpCode::Negate => out.push(I_F64_NEG),
+            // Only ever the script's return value, which goes unused.
+            OpCode::Nil => {}
+            OpCode::True
+            | OpCode::False
+            | OpCode::Equal
+            | OpCode::Greater
+            | OpCode::Less
+            | OpCode::Not
+            | OpCode::Jump
+            | OpCode::JumpIfFalse => unreachable!("rejected by compile"),
+            OpCode::Pop => out.push(I_DROP),
+            OpCode::Print => {
+                out.push(I_CALL);
+                write_u32(&mut out, PRINT);
+            }
+            OpCode::Return => {
+                out.push(I_RETURN);
+                return out;
+            }
+        }
+    }
+}
+
+fn section(module: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    module.push(id);
+    write_u32(module, contents.len() as u32);
+    module.extend_from_slice(contents);
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    write_u32(out, name.len() as u32);
+    out.extend_from_slice(name.as_bytes());
+}
+
+/// Unsigned LEB128.
+fn write_u32(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+
+    #[test]
+    fn leb128() {
+        let encode = |value| {
+            let mut out = Vec::new();
+            write_u32(&mut out, value);
+            out
+        };
+        assert_eq!(encode(0), [0]);
+        assert_eq!(encode(127), [0x7f]);
+        assert_eq!(encode(128), [0x80, 0x01]);
+        assert_eq!(encode(624_485), [0xe5, 0x8e, 0x26]);
+    }
+
+    #[test]
+    fn compiles_expression() {
+        let chunk = Compiler::new("print -2, 3;").compile().unwrap();
+        let module = compile(&chunk).unwrap();
+        assert!(module.starts_with(b"\0asm\x01\0\0\0"));
+
+        let mut body = vec![I_F64_CONST];
+        body.extend_from_slice(&2.0f64.to_le_bytes());
+        body.extend_from_slice(&[I_F64_NEG, I_DROP, I_F64_CONST]);
+        body.extend_from_slice(&3.0f64.to_le_bytes());
+        body.extend_from_slice(&[I_CALL, 0, I_RETURN, I_END]);
+        assert!(module.ends_with(&body));
+    }
+
+    #[test]
+    fn rejects_invalid_chunks() {
+        let mut chunk = Chunk::new();
+        chunk.write(OpCode::Add as u8, 1);
+        chunk.write(OpCode::Return as u8, 1);
+        assert!(compile(&chunk).is_err());
+    }
+
+    #[test]
+    fn rejects_non_numbers() {
+        let chunk = Compiler::new("print 1 < 2;").compile().unwrap();
+        assert_eq!(
+            compile(&chunk),
+            Err("The wasm backend only supports numbers, not OP_LESS.".to_owned())
+        );
+        let chunk = Compiler::new("print nil;").compile().unwrap();
+        assert_eq!(
+            compile(&chunk),
+            Err("The wasm backend only supports numbers, not OP_NIL.".to_owned())
+        );
+        let chunk = Compiler::new("print \"a\";").compile().unwrap();
+        assert_eq!(
+            compile(&chunk),
+            Err("The wasm backend only supports numbers, not String.".to_owned())
+        );
+        let chunk = Compiler::new("print 1 or 2;").compile().unwrap();
+        assert_eq!(
+            compile(&chunk),
+            Err("The wasm backend does not support control flow yet.".to_owned())
+        );
+    }
+}
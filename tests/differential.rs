@@ -0,0 +1,60 @@
+//! Runs every program in `tests/corpus` through both the tree-walking
+//! interpreter and the bytecode VM and checks that they agree.
+
+use std::fs;
+use std::path::Path;
+
+use rlox::capture::SharedBuffer;
+use rlox::treewalk::Interpreter;
+use rlox::vm::VM;
+
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    /// Exit status, which stands for the class of error.
+    status: u8,
+    stdout: String,
+}
+
+fn run_vm(source: &str) -> Outcome {
+    let stdout = SharedBuffer::new();
+    let mut vm = VM::new();
+    vm.set_output(Box::new(stdout.clone()));
+    let result = vm.interpret(source);
+    Outcome {
+        status: result.exit_code(),
+        stdout: stdout.contents(),
+    }
+}
+
+fn run_treewalk(source: &str) -> Outcome {
+    let stdout = SharedBuffer::new();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(Box::new(stdout.clone()));
+    let result = interpreter.interpret(source);
+    Outcome {
+        status: result.exit_code(),
+        stdout: stdout.contents(),
+    }
+}
+
+/// Asserts that both backends produce the same stdout and error class.
+fn assert_backends_agree(name: &str, source: &str) {
+    let vm = run_vm(source);
+    let treewalk = run_treewalk(source);
+    assert_eq!(vm, treewalk, "backends disagree on {name}");
+}
+
+#[test]
+fn corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut count = 0;
+    for entry in fs::read_dir(dir).expect("failed to read corpus") {
+        let path = entry.expect("failed to read corpus entry").path();
+        if path.extension().is_some_and(|ext| ext == "lox") {
+            let source = fs::read_to_string(&path).expect("failed to read program");
+            assert_backends_agree(&path.display().to_string(), &source);
+            count += 1;
+        }
+    }
+    assert!(count > 0, "corpus is empty");
+}
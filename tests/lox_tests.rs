@@ -0,0 +1,23 @@
+//! Runs the `.lox` files in `tests/lox` against their expectation comments.
+
+use std::path::Path;
+
+use rlox::test_runner;
+
+#[test]
+fn lox_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    let summary = test_runner::run_path(&dir).expect("failed to run tests");
+    for (path, failures) in &summary.failed {
+        eprintln!("FAIL {}", path.display());
+        for failure in failures {
+            eprintln!("    {failure}");
+        }
+    }
+    assert!(
+        summary.is_success(),
+        "{} test file(s) failed",
+        summary.failed.len()
+    );
+    assert!(summary.passed > 0, "no test files found");
+}
@@ -0,0 +1,68 @@
+//! Runs the official craftinginterpreters test corpus through the
+//! expectation-comment harness.
+//!
+//! The corpus is not vendored. Point `RLOX_OFFICIAL_TESTS` at the `test`
+//! directory of a craftinginterpreters checkout to enable this test:
+//!
+//! ```sh
+//! git clone https://github.com/munificent/craftinginterpreters
+//! RLOX_OFFICIAL_TESTS=craftinginterpreters/test cargo test --test official_suite -- --nocapture
+//! ```
+
+use std::env;
+use std::path::Path;
+
+use rlox::test_runner;
+
+/// Chapters whose tests currently pass, each with the corpus paths (files or
+/// directories, relative to `test/`) the book's own runner enables for it,
+/// minus any that need features not implemented yet. Extend this as the
+/// implementation grows.
+///
+/// The chapter 17 to 20 runners only check `expressions/evaluate.lox`,
+/// which is a bare expression and stopped being a valid program once
+/// scripts became statements.
+const PASSING_CHAPTERS: &[(&str, &[&str])] = &[(
+    "chap21_global",
+    &[
+        "bool/equality.lox",
+        "bool/not.lox",
+        "empty_file.lox",
+        "nil/literal.lox",
+        "precedence.lox",
+    ],
+)];
+
+#[test]
+fn official_suite() {
+    let Some(root) = env::var_os("RLOX_OFFICIAL_TESTS") else {
+        eprintln!("RLOX_OFFICIAL_TESTS is not set; skipping the official test suite.");
+        return;
+    };
+    let root = Path::new(&root);
+
+    let mut failures = Vec::new();
+    for (chapter, paths) in PASSING_CHAPTERS {
+        let mut passed = 0;
+        for path in *paths {
+            let path = root.join(path);
+            let summary = test_runner::run_path(&path)
+                .unwrap_or_else(|e| panic!("failed to run {}: {e}", path.display()));
+            passed += summary.passed;
+            failures.extend(summary.failed);
+        }
+        println!("{chapter}: {passed} passed");
+    }
+
+    for (path, errors) in &failures {
+        eprintln!("FAIL {}", path.display());
+        for error in errors {
+            eprintln!("    {error}");
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} official test(s) regressed",
+        failures.len()
+    );
+}
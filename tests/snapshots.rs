@@ -0,0 +1,54 @@
+//! Compiles every fixture in `tests/disasm` and compares its disassembly
+//! against the checked-in `.disasm` snapshot next to it.
+//!
+//! Run with `UPDATE_SNAPSHOTS=1` to rewrite the snapshots after an
+//! intentional codegen change, then review the diff.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use rlox::compiler::Compiler;
+
+#[test]
+fn disassembly_snapshots() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/disasm");
+    let update = env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let mut mismatches = Vec::new();
+
+    let mut fixtures: Vec<_> = fs::read_dir(&dir)
+        .expect("failed to read fixtures")
+        .map(|entry| entry.expect("failed to read fixture").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lox"))
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty(), "no fixtures found");
+
+    for fixture in fixtures {
+        let source = fs::read_to_string(&fixture).expect("failed to read fixture");
+        let chunk = Compiler::new(&source)
+            .compile()
+            .unwrap_or_else(|| panic!("{} failed to compile", fixture.display()));
+        let actual = chunk.disassembly("code");
+
+        let snapshot = fixture.with_extension("disasm");
+        if update {
+            fs::write(&snapshot, &actual).expect("failed to write snapshot");
+            continue;
+        }
+        match fs::read_to_string(&snapshot) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => mismatches.push(format!(
+                "{}\n--- expected\n{expected}--- actual\n{actual}",
+                snapshot.display()
+            )),
+            Err(_) => mismatches.push(format!("{} is missing", snapshot.display())),
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "disassembly changed (rerun with UPDATE_SNAPSHOTS=1 to accept):\n{}",
+        mismatches.join("\n")
+    );
+}